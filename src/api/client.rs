@@ -7,8 +7,10 @@ use hardware_id;
 use uuid::Uuid;
 use serde::{self, de::{MapAccess, Visitor}, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
 use sha256::digest;
+use signalrs_client::hub::arguments::HubArgument;
+use signalrs_derive::HubArgument;
 
-use crate::{backend::thread::SessionUpdate, CONTACTS_LIST, MESSAGE_CACHE, SESSION_CACHE};
+use crate::{backend::thread::{SessionUpdate, UserStatus}, rich_text::strip_tags, CONTACTS_LIST, ENDPOINTS, MESSAGE_CACHE, SESSION_CACHE, THIS_FUCKING_SUCKS, USER_STATUSES};
 
 pub struct Client {
     req: Option<reqwest::Client>,
@@ -16,9 +18,23 @@ pub struct Client {
     pub hwid: String,
     pub logged_in: bool,
     pub user_id: Option<String>,
-    token: Option<String>
+    token: Option<String>,
+    /// who we logged in as, for re-authenticating
+    username: Option<String>,
+    /// when the server says our token dies
+    expire: Option<DateTime<Utc>>,
+    /// how long a session lasts, taken from the login response
+    session_lifetime: Option<chrono::Duration>,
+    last_extended: Option<DateTime<Utc>>,
+    /// set when a request bounces with 401/403, makes the next keepalive happen right away
+    auth_rejected: bool,
 }
 
+/// How often we extend the session, same interval the official client uses
+const SESSION_EXTEND_INTERVAL: i64 = 3600;
+/// Extend early if we're this close (in seconds) to expiring, regardless of the interval
+const SESSION_EXPIRY_MARGIN: i64 = 600;
+
 #[derive(serde::Serialize, serde::Deserialize, std::fmt::Debug)]
 #[serde(untagged)]
 pub enum UserSessionsAuthReq {
@@ -49,8 +65,8 @@ struct UserSessionsReq {
 struct Entity {
     user_id: String,
     token: String,
-    created: String,
-    expire: String,
+    created: ResDateTime,
+    expire: ResDateTime,
     remember_me: bool,
     secret_machine_id_hash: String,
     secret_machine_id_salt: String,
@@ -120,12 +136,28 @@ pub struct UserInfo {
     pub profile: Option<UserProfile>,
 }
 
+/// Our side of a contact relationship
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ContactStatus {
+    None,
+    SearchResult,
+    /// They sent us a friend request
+    Requested,
+    Ignored,
+    Blocked,
+    /// We're friends, or we've sent them a request (see [`Contact::is_accepted`])
+    Accepted,
+    /// Anything the server adds later, so one new status doesn't sink the whole contacts list
+    #[serde(other)]
+    Unknown,
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Contact {
     pub id: String,
     pub contact_username: String,
-    pub contact_status: String,
+    pub contact_status: ContactStatus,
     pub is_accepted: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile: Option<UserProfile>,
@@ -135,7 +167,52 @@ pub struct Contact {
     pub owner_id: String,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+/// Things you can do to someone's contact entry, all go through the hub's `UpdateContact`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContactAction {
+    /// Send a friend request
+    Add,
+    /// Accept one they sent
+    Accept,
+    /// Decline one they sent
+    Ignore,
+    /// Unfriend, cancel a request we sent, or unblock
+    Remove,
+    Block,
+}
+
+impl Contact {
+    pub fn is_friend(&self) -> bool {
+        self.contact_status == ContactStatus::Accepted && self.is_accepted
+    }
+
+    pub fn is_incoming_request(&self) -> bool {
+        self.contact_status == ContactStatus::Requested
+    }
+
+    pub fn is_outgoing_request(&self) -> bool {
+        self.contact_status == ContactStatus::Accepted && !self.is_accepted
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        self.contact_status == ContactStatus::Blocked
+    }
+
+    /// Short description of where we stand with them, if it's anything other than plain friends
+    pub fn relationship_label(&self) -> Option<&'static str> {
+        if self.is_incoming_request() {
+            Some("Sent you a friend request")
+        } else if self.is_outgoing_request() {
+            Some("Friend request sent")
+        } else if self.is_blocked() {
+            Some("Blocked")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
 pub enum MessageType {
     Text,
     Object,
@@ -187,7 +264,7 @@ impl<'de> Deserialize<'de> for ResDateTime {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, HubArgument)]
 #[serde(rename_all = "camelCase")]
 pub struct Message {
     pub id: String,
@@ -204,9 +281,133 @@ pub struct Message {
     pub owner_id: String,
 }
 
+impl Message {
+    /// A text message from us, with an ID made up on our end so it can be shown before the server's seen it
+    pub fn new_text(sender_id: &str, recipient_id: &str, content: String) -> Self {
+        let now = ResDateTime(Utc::now());
+        Self {
+            id: format!("MSG-{}", Uuid::new_v4()),
+            sender_id: sender_id.to_owned(),
+            recipient_id: recipient_id.to_owned(),
+            other_id: recipient_id.to_owned(),
+            message_type: MessageType::Text,
+            content,
+            send_time: now.clone(),
+            last_update_time: now,
+            read_time: None,
+            is_migrated: true,
+            owner_id: sender_id.to_owned(),
+        }
+    }
+
+    /// One line summary, for the messages list and notifications
+    pub fn preview(&self) -> String {
+        match self.message_type {
+            MessageType::Text => strip_tags(&self.content),
+            MessageType::Object => match self.record() {
+                Some(record) => format!("Shared {}", strip_tags(&record.name)),
+                None => "Shared content".to_owned(),
+            },
+            MessageType::Sound => "Voice message".to_owned(),
+            MessageType::SessionInvite => match self.session_invite() {
+                Some(session) => format!("Invite to {}", strip_tags(&session.name)),
+                None => "[Session Invite]".to_owned(),
+            },
+        }
+    }
+
+    /// The item an object message is sharing, or the audio clip a voice message points at.
+    /// None if this isn't one of those or it didn't parse
+    pub fn record(&self) -> Option<Record> {
+        if !matches!(self.message_type, MessageType::Object | MessageType::Sound) { return None; }
+        serde_json::from_str(&self.content).ok()
+    }
+
+    /// The session an invite's for, the content is the same JSON the hub sends session updates as.
+    /// None if this isn't an invite or it didn't parse
+    pub fn session_invite(&self) -> Option<SessionUpdate> {
+        if self.message_type != MessageType::SessionInvite { return None; }
+        serde_json::from_str(&self.content).ok()
+    }
+}
+
+/// An item/world/whatever in someone's inventory, what object messages carry.
+/// Only the bits we show, the real thing has a lot more
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    pub id: String,
+    pub owner_id: String,
+    #[serde(default)]
+    pub owner_name: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// `object`, `world`, `directory`, `link`...
+    #[serde(default)]
+    pub record_type: String,
+    /// Inventory folder it lives in, backslash separated
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub asset_uri: Option<String>,
+    #[serde(default)]
+    pub thumbnail_uri: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Record {
+    /// What the game takes to find this record again
+    pub fn link(&self) -> String {
+        format!("resrec:///{}/{}", self.owner_id, self.id)
+    }
+}
+
+/// How many messages a conversation's history grows by each time you scroll to the top
+pub const MESSAGE_PAGE_SIZE: u32 = 50;
+
+/// Filters for `users/{id}/messages`, anything left as `None` is up to the server
+#[derive(Debug, Clone, Default)]
+pub struct MessageQuery {
+    /// Newest `max_items` messages get returned
+    pub max_items: Option<u32>,
+    /// Only messages sent after this
+    pub from_time: Option<DateTime<Utc>>,
+    /// Only the conversation with this user
+    pub user: Option<String>,
+}
+
+impl MessageQuery {
+    /// One conversation's newest `max_items` messages
+    pub fn conversation(user: &str, max_items: u32) -> Self {
+        Self { max_items: Some(max_items), from_time: None, user: Some(user.to_owned()) }
+    }
+
+    fn to_query_string(&self) -> String {
+        let mut params: Vec<String> = Vec::new();
+        if let Some(max_items) = self.max_items {
+            params.push(format!("maxItems={}", max_items));
+        }
+        if let Some(from_time) = self.from_time {
+            // Z instead of +00:00, so there's no plus sign to get mangled into a space
+            params.push(format!("fromTime={}", from_time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)));
+        }
+        if let Some(user) = &self.user {
+            params.push(format!("user={}", user));
+        }
+
+        if params.is_empty() { String::new() } else { format!("?{}", params.join("&")) }
+    }
+}
+
 #[derive(std::fmt::Debug)]
 pub enum LoginError {
     InvalidCredentials,
+    /// Account has 2FA on, ask for a code and try again
+    TotpRequired,
+    /// We sent a code, it wasn't right
+    InvalidTotp,
     JsonParseFailed,
     RequestFailed,
     ReachedTheEnd
@@ -216,6 +417,8 @@ impl fmt::Display for LoginError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", match self {
             LoginError::InvalidCredentials => "Invalid Credentials",
+            LoginError::TotpRequired => "Two-factor Code Required",
+            LoginError::InvalidTotp => "Invalid Two-factor Code",
             LoginError::JsonParseFailed => "JSON Parse Failed",
             LoginError::RequestFailed => "Request Failed",
             LoginError::ReachedTheEnd => "Pipe Bomb in Mailbox",
@@ -224,15 +427,82 @@ impl fmt::Display for LoginError {
 }
 
 #[derive(std::fmt::Debug)]
-pub enum UserInfoError {
-    /// Profile JSON parsing failed
-    JsonParseFailed,
-    /// Request failed to send
-    RequestFailed,
-    /// Either no profile for UID, or no matches for username
-    NoResults,
+pub enum ApiError {
     /// No API connector is present
-    NoClient
+    NoClient,
+    /// Tried to hit an authenticated endpoint without a user ID and token
+    NotLoggedIn,
+    /// Request failed to send, or the body never showed up
+    Request { endpoint: String, source: reqwest::Error },
+    /// Server answered with something other than a success
+    Status { endpoint: String, status: reqwest::StatusCode, body: String },
+    /// 401/403, our token isn't valid anymore
+    AuthExpired { endpoint: String, status: reqwest::StatusCode },
+    /// Server answered, but not with anything we understand
+    Deserialize { endpoint: String, body: String, source: serde_json::Error },
+    /// Either no profile for UID, or no matches for username
+    NoResults { endpoint: String },
+}
+
+/// How much of a response body we hang on to for error reporting
+const BODY_EXCERPT_LEN: usize = 256;
+
+fn body_excerpt(body: &str) -> String {
+    if body.chars().count() <= BODY_EXCERPT_LEN {
+        body.to_owned()
+    } else {
+        format!("{}…", body.chars().take(BODY_EXCERPT_LEN).collect::<String>())
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NoClient => write!(f, "No API client"),
+            ApiError::NotLoggedIn => write!(f, "Not logged in"),
+            ApiError::Request { endpoint, source } => write!(f, "/{}: request failed ({})", endpoint, source),
+            ApiError::Status { endpoint, status, body } => {
+                if body.is_empty() { write!(f, "/{}: {}", endpoint, status) } else { write!(f, "/{}: {} \"{}\"", endpoint, status, body) }
+            },
+            ApiError::AuthExpired { endpoint, status } => write!(f, "/{}: session expired ({})", endpoint, status),
+            ApiError::Deserialize { endpoint, source, .. } => write!(f, "/{}: couldn't parse response ({})", endpoint, source),
+            ApiError::NoResults { endpoint } => write!(f, "/{}: no results", endpoint),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Request { source, .. } => Some(source),
+            ApiError::Deserialize { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl ApiError {
+    /// Short line for notifications, the full thing goes through Display
+    pub fn summary(&self) -> &'static str {
+        match self {
+            ApiError::NoClient => "No API client",
+            ApiError::NotLoggedIn => "Not logged in",
+            ApiError::Request { .. } => "Couldn't reach the server",
+            ApiError::Status { .. } => "Server error",
+            ApiError::AuthExpired { .. } => "Session expired",
+            ApiError::Deserialize { .. } => "Unexpected response",
+            ApiError::NoResults { .. } => "No results",
+        }
+    }
+}
+
+/// Deserializes `jason`, keeping an excerpt of it around if it goes wrong
+fn parse_json<T: serde::de::DeserializeOwned>(endpoint: &str, jason: &str) -> Result<T, ApiError> {
+    serde_json::from_str(jason).map_err(|source| ApiError::Deserialize {
+        endpoint: endpoint.to_owned(),
+        body: body_excerpt(jason),
+        source
+    })
 }
 
 impl Client {
@@ -262,15 +532,27 @@ impl Client {
             logged_in: false,
             user_id: None,
             token: None,
+            username: None,
+            expire: None,
+            session_lifetime: None,
+            last_extended: None,
+            auth_rejected: false,
         }
     }
 
     /// Returns your token if sucessfull
-    pub async fn login(&mut self, username: &str, auth_variant: UserSessionsAuthReq, remember_me: bool) -> Result<String, LoginError> {
+    ///
+    /// `totp` is the 6 digit code from an authenticator app, only needed once the server's said [`LoginError::TotpRequired`]
+    pub async fn login(&mut self, username: &str, auth_variant: UserSessionsAuthReq, remember_me: bool, totp: Option<&str>) -> Result<String, LoginError> {
         if let Some(client) = &self.req {
             let mut headers = header::HeaderMap::new();
             headers.insert("UID", header::HeaderValue::from_str(&self.hwid).expect("man"));
             headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+            if let Some(totp) = totp {
+                if let Ok(code) = header::HeaderValue::from_str(totp.trim()) {
+                    headers.insert("TOTP", code);
+                } else { return Err(LoginError::InvalidTotp) }
+            }
 
             let body: UserSessionsReq = UserSessionsReq {
                 username: username.to_owned(),
@@ -278,7 +560,7 @@ impl Client {
                 secret_machine_id: self.uuid.to_owned().into(),
                 remember_me
             };
-            let request = client.post("https://api.resonite.com/userSessions")
+            let request = client.post(ENDPOINTS.api_url("userSessions"))
             .body(serde_json::to_string(&body).unwrap()).headers(headers);
         
 
@@ -288,63 +570,54 @@ impl Client {
             
             let jason: &str = if let Ok(string) = std::str::from_utf8(&jason_bytes) { string } else { return Err(LoginError::JsonParseFailed) };
             if jason.eq("Login.InvalidCredentials") { return Err(LoginError::InvalidCredentials) }
+            // 2FA accounts get a bare "TOTP" back (403) until the header's there and right
+            if jason.trim().trim_matches('"').eq_ignore_ascii_case("TOTP") {
+                return Err(if totp.is_some() { LoginError::InvalidTotp } else { LoginError::TotpRequired })
+            }
             let resp: LoginResponse = if let Ok(resp) = serde_json::from_str(jason) { resp } else { println!("{:?}", jason); return Err(LoginError::JsonParseFailed) };
             
             self.user_id = Some(resp.entity.user_id.clone());
             self.logged_in = true;
             self.token = Some(resp.entity.token.clone());
-
-            
+            self.username = Some(username.to_owned());
+            self.expire = Some(resp.entity.expire.0);
+            self.session_lifetime = Some(resp.entity.expire.0 - resp.entity.created.0);
+            self.last_extended = Some(Utc::now());
+            self.auth_rejected = false;
 
             return Ok(resp.entity.token)
         }
         Err(LoginError::ReachedTheEnd)
     }
 
-    pub async fn get_users(&mut self, id: &str) -> Result<Vec<UserInfo>, UserInfoError>{
+    pub async fn get_users(&mut self, id: &str) -> Result<Vec<UserInfo>, ApiError> {
         let is_by_username = !id.to_lowercase().get(..2).eq(&Some("u-"));
 
-        let jason = if let Some(guh) = self.get_json(&format!("users{}", if is_by_username { format!("?name={}", id) } else { format!("/{}", id) })).await {
-            guh
-        } else { return Err(UserInfoError::RequestFailed); };
-
-        if jason.eq("Invalid User ID") { return Err(UserInfoError::NoResults); }
-
-        let user: Result<Vec<UserInfo>, serde_json::Error> = serde_json::from_str(&jason);
-        if let Ok(user) = user {
-            return Ok(user);
-        } else {
-            println!("couldn't deserialize data: {}", user.err().unwrap());
-            println!("{}", jason);
-            Err(UserInfoError::JsonParseFailed)
+        if !is_by_username {
+            // by ID only ever gets one, but the search page doesn't care
+            return Ok(vec![self.get_user(id).await?]);
         }
+
+        let endpoint = format!("users?name={}", id);
+        let jason = self.get_json(&endpoint).await?;
+        let users: Vec<UserInfo> = parse_json(&endpoint, &jason)?;
+        if users.is_empty() { return Err(ApiError::NoResults { endpoint }); }
+        Ok(users)
     }
 
-    pub async fn get_user(&mut self, id: &str) -> Result<UserInfo, UserInfoError>{
-        let jason = if let Some(guh) = self.get_json(&format!("users/{}", id)).await {
-            guh
-        } else { return Err(UserInfoError::RequestFailed); };
+    pub async fn get_user(&mut self, id: &str) -> Result<UserInfo, ApiError> {
+        let endpoint = format!("users/{}", id);
+        let jason = self.get_json(&endpoint).await?;
 
-        if jason.eq("Invalid User ID") { return Err(UserInfoError::NoResults); }
+        if jason.eq("Invalid User ID") { return Err(ApiError::NoResults { endpoint }); }
 
-        let user: Result<UserInfo, serde_json::Error> = serde_json::from_str(&jason);
-        if let Ok(user) = user {
-            return Ok(user);
-        } else {
-            println!("couldn't deserialize data: {}", user.err().unwrap());
-            println!("{}", jason);
-            Err(UserInfoError::JsonParseFailed)
-        }
+        parse_json(&endpoint, &jason)
     }
 
-    pub async fn get_contacts(&mut self, id: &str) {
-        let jason = if let Some(guh) = self.get_json(&format!("users/{}/contacts", id)).await {
-            guh
-        } else { return; };
-
-        let user_parse_res = serde_json::from_str(&jason);
-
-        let users: Vec<Contact> = if let Ok(res) = user_parse_res { res } else { println!("{}", user_parse_res.err().unwrap()); println!("{}", jason); return; };
+    pub async fn get_contacts(&mut self, id: &str) -> Result<(), ApiError> {
+        let endpoint = format!("users/{}/contacts", id);
+        let jason = self.get_json(&endpoint).await?;
+        let users: Vec<Contact> = parse_json(&endpoint, &jason)?;
 
         {
             let mut list = CONTACTS_LIST.lock();
@@ -352,46 +625,32 @@ impl Client {
                 list.insert(user.id.clone(), user);
             }   
         }
+        Ok(())
     }
 
-    pub async fn get_messages(&mut self, id: &str) {
-        let jason = if let Some(guh) = self.get_json(&format!("users/{}/messages", id)).await {
-            guh
-        } else { return; };
-
-        let messages_parse_res = serde_json::from_str(&jason);
-        let messages: Vec<Message> = if let Ok(res) = messages_parse_res { res } else { println!("{}", messages_parse_res.err().unwrap()); println!("{}", jason); return; };
-
-        {
-            let mut cache = MESSAGE_CACHE.lock();
-            for message in messages {
-                let vec = if let Some(vec) = cache.get_mut(&message.other_id) {
-                    vec
-                } else {
-                    cache.insert(message.other_id.clone(), Vec::new());
-                    cache.get_mut(&message.other_id).expect("bro")
-                };
-
-                vec.push(message);
-            }
+    /// Fetches messages for user `id` matching `query` into the cache, returns how many the server sent back
+    pub async fn get_messages(&mut self, id: &str, query: &MessageQuery) -> Result<usize, ApiError> {
+        let endpoint = format!("users/{}/messages{}", id, query.to_query_string());
+        let jason = self.get_json(&endpoint).await?;
+        let messages: Vec<Message> = parse_json(&endpoint, &jason)?;
+        let count = messages.len();
 
-            for (_, msg) in cache.iter_mut() {
-                msg.sort_by(|a, b| a.last_update_time.0.cmp(&b.last_update_time.0));
-            }
-        }
+        MESSAGE_CACHE.lock().extend(messages);
+        Ok(count)
     }
 
-    pub async fn get_status(&mut self, id: &str) {
-        if let Some(guh) = self.get_json(&format!("users/{}/status", id)).await {
-            println!("user: {}", guh);
-        }
+    /// Same thing the hub sends with `ReceiveStatusUpdate`, cached the same way
+    pub async fn get_status(&mut self, id: &str) -> Result<UserStatus, ApiError> {
+        let endpoint = format!("users/{}/status", id);
+        let jason = self.get_json(&endpoint).await?;
+        let status: UserStatus = parse_json(&endpoint, &jason)?;
+        USER_STATUSES.lock().insert(id.to_owned(), status.clone());
+        Ok(status)
     }
 
-    pub async fn get_sessions(&mut self) {
-        let jason = if let Some(guh) = self.get_json("sessions").await { guh } else { return; };
-        
-        let sesisons_parse_res = serde_json::from_str(&jason);
-        let sessions: Vec<SessionUpdate> = if let Ok(res) = sesisons_parse_res { res } else { println!("{}", sesisons_parse_res.err().unwrap()); println!("{}", jason); return; };
+    pub async fn get_sessions(&mut self) -> Result<(), ApiError> {
+        let jason = self.get_json("sessions").await?;
+        let sessions: Vec<SessionUpdate> = parse_json("sessions", &jason)?;
 
         {
             let mut cache = SESSION_CACHE.lock();
@@ -399,29 +658,146 @@ impl Client {
                 cache.insert(session.session_id.clone(), session);
             }
         }
+        Ok(())
     }
 
-    async fn get_json(&mut self, endpoint: &str) -> Option<String> {
-        let client = if let Some(client) = &self.req { client } else { return None };
+    /// Whether it's time to [`Client::extend_session`], either on the regular interval, because
+    /// we're about to expire, or because the server's already started rejecting us
+    pub fn needs_extension(&self) -> bool {
+        if !self.logged_in { return false; }
+        if self.auth_rejected { return true; }
+        let now = Utc::now();
+        let interval_passed = self.last_extended.map_or(true, |last| (now - last).num_seconds() >= SESSION_EXTEND_INTERVAL);
+        let expiring = self.expire.map_or(false, |expire| (expire - now).num_seconds() <= SESSION_EXPIRY_MARGIN);
+        interval_passed || expiring
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expire
+    }
+
+    /// Pushes the session expiry out, the server doesn't give us the new expiry so we assume it's
+    /// been reset to the lifetime we saw at login
+    pub async fn extend_session(&mut self) -> Result<(), ApiError> {
+        self.send_json(Method::PATCH, "userSessions", None).await?;
+        let now = Utc::now();
+        self.last_extended = Some(now);
+        if let Some(lifetime) = self.session_lifetime {
+            self.expire = Some(now + lifetime);
+        }
+        self.auth_rejected = false;
+        Ok(())
+    }
+
+    /// Logs in again with a stored session token, for when [`Client::extend_session`] didn't cut it
+    pub async fn reauthenticate(&mut self, session_token: String) -> Result<String, LoginError> {
+        let username = if let Some(username) = self.username.clone() { username } else { return Err(LoginError::InvalidCredentials) };
+        self.login(&username, UserSessionsAuthReq::Token {
+            _type: "sessionToken".to_owned(), session_token
+        }, true, None).await
+    }
+
+    /// Builds the contact entry the hub expects for `action`. `existing` is what we already have for them, if anything
+    pub fn contact_update(&self, existing: Option<&Contact>, id: &str, username: &str, action: ContactAction) -> Result<Contact, ApiError> {
+        let owner_id = if let Some(owner_id) = &self.user_id { owner_id.clone() } else { return Err(ApiError::NotLoggedIn) };
+
+        let mut contact = if let Some(existing) = existing {
+            existing.clone()
+        } else {
+            Contact {
+                id: id.to_owned(),
+                contact_username: username.to_owned(),
+                contact_status: ContactStatus::None,
+                is_accepted: false,
+                profile: None,
+                latest_message_time: ResDateTime(Utc::now()),
+                is_migrated: true,
+                is_counterpart_migrated: true,
+                owner_id,
+            }
+        };
+
+        // is_accepted is their side of things, we only ever touch ours
+        contact.contact_status = match action {
+            ContactAction::Add | ContactAction::Accept => ContactStatus::Accepted,
+            ContactAction::Ignore => ContactStatus::Ignored,
+            ContactAction::Remove => ContactStatus::None,
+            ContactAction::Block => ContactStatus::Blocked,
+        };
+
+        Ok(contact)
+    }
+
+    /// Revokes our session server-side and forgets everything we knew about the account.
+    /// Local state gets cleared even if the server call fails, the token's useless to us either way
+    pub async fn logout(&mut self) -> Result<(), ApiError> {
+        let result = match (self.user_id.clone(), self.token.clone()) {
+            (Some(user_id), Some(token)) => self.send_json(Method::DELETE, &format!("userSessions/{}/{}", user_id, token), None).await.map(|_| ()),
+            _ => Err(ApiError::NotLoggedIn),
+        };
+
+        self.clear_session();
+        result
+    }
+
+    /// Forgets the account locally, without telling the server anything
+    pub fn clear_session(&mut self) {
+        self.user_id = None;
+        self.token = None;
+        self.logged_in = false;
+        self.username = None;
+        self.expire = None;
+        self.session_lifetime = None;
+        self.last_extended = None;
+        self.auth_rejected = false;
+
+        CONTACTS_LIST.lock().clear();
+        MESSAGE_CACHE.lock().clear();
+        SESSION_CACHE.lock().clear();
+        USER_STATUSES.lock().clear();
+        THIS_FUCKING_SUCKS.lock().clear();
+    }
+
+    async fn get_json(&mut self, endpoint: &str) -> Result<String, ApiError> {
+        self.send_json(Method::GET, endpoint, None).await
+    }
+
+    /// Authenticated request to `endpoint`, `body` is sent as-is with a JSON content type
+    async fn send_json(&mut self, method: Method, endpoint: &str, body: Option<String>) -> Result<String, ApiError> {
+        let client = if let Some(client) = &self.req { client } else { return Err(ApiError::NoClient) };
+
+        let (user_id, token) = match (&self.user_id, &self.token) {
+            (Some(user_id), Some(token)) => (user_id, token),
+            _ => return Err(ApiError::NotLoggedIn),
+        };
 
         let mut headers = header::HeaderMap::new();
         headers.insert("UID", header::HeaderValue::from_str(&self.hwid).expect("man"));
         headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
-        headers.insert("Authorization", if let Ok(res) = header::HeaderValue::from_str(&format!("res {}:{}", 
-            if let Some(res) = &self.user_id.clone() { res } else {return None },
-            if let Some(res) = &self.token.clone() { res } else {return None })) { res } else { return None }
-        );
+        headers.insert("Authorization", header::HeaderValue::from_str(&format!("res {}:{}", user_id, token)).map_err(|_| ApiError::NotLoggedIn)?);
 
-        let request = client
-        .get(format!("https://api.resonite.com/{}", endpoint))
+        let mut request = client
+        .request(method, ENDPOINTS.api_url(endpoint))
         .headers(headers);
+        if let Some(body) = body {
+            request = request.body(body);
+        }
 
-        let response = if let Ok(res) = request.send().await { res } else { println!("boowomp"); return None };
-        if response.status().is_client_error() { println!("/{} errored! {:?}", endpoint, response.error_for_status()); return None }
-        
-        
-        let jason_bytes = if let Ok(res) = response.bytes().await { res } else { return None };
-        let jason: &str = if let Ok(res) = std::str::from_utf8(&jason_bytes) { res } else { return None };
-        Some(jason.to_owned())
+        let response = request.send().await.map_err(|source| ApiError::Request { endpoint: endpoint.to_owned(), source })?;
+        let status = response.status();
+        let jason = response.text().await.map_err(|source| ApiError::Request { endpoint: endpoint.to_owned(), source })?;
+
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            self.auth_rejected = true;
+            return Err(ApiError::AuthExpired { endpoint: endpoint.to_owned(), status });
+        }
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(ApiError::NoResults { endpoint: endpoint.to_owned() });
+        }
+        if !status.is_success() {
+            return Err(ApiError::Status { endpoint: endpoint.to_owned(), status, body: body_excerpt(&jason) });
+        }
+
+        Ok(jason)
     }
 }
\ No newline at end of file
@@ -0,0 +1,89 @@
+use std::{env, fs, path::PathBuf};
+
+use directories::ProjectDirs;
+
+/// Where ResBox talks to. Defaults to the real Resonite servers, but can be pointed elsewhere
+/// (a local mock, staging, etc) with a config file or environment variables.
+///
+/// Load order, later wins:
+/// 1. built-in defaults
+/// 2. `endpoints.json` in the config dir (or whatever `RESBOX_ENDPOINTS_FILE` points at)
+/// 3. `RESBOX_API_URL`, `RESBOX_ASSETS_URL`, `RESBOX_HUB_HOST`, `RESBOX_HUB_PORT`, `RESBOX_HUB_PATH`, `RESBOX_HUB_UNENCRYPTED`
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Endpoints {
+    /// REST base, e.g. `https://api.resonite.com/`
+    pub api: String,
+    /// Asset CDN base that `resdb:///` hashes get appended to
+    pub assets: String,
+    /// SignalR host, no scheme
+    pub hub_host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hub_port: Option<u16>,
+    pub hub_path: String,
+    /// Plain ws/http instead of wss/https, for local stand-ins
+    pub hub_unencrypted: bool,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            api: "https://api.resonite.com/".to_owned(),
+            assets: "https://assets.resonite.com/".to_owned(),
+            hub_host: "api.resonite.com".to_owned(),
+            hub_port: None,
+            hub_path: "hub".to_owned(),
+            hub_unencrypted: false,
+        }
+    }
+}
+
+impl Endpoints {
+    pub fn load() -> Self {
+        let mut endpoints = if let Some(path) = Self::config_path() {
+            match fs::read_to_string(&path) {
+                Ok(jason) => match serde_json::from_str(&jason) {
+                    Ok(parsed) => { println!("using endpoints from {}", path.to_string_lossy()); parsed },
+                    Err(err) => { println!("couldn't parse {}: {}", path.to_string_lossy(), err); Self::default() },
+                },
+                Err(_) => Self::default(), // not having one is the normal case
+            }
+        } else { Self::default() };
+
+        if let Ok(api) = env::var("RESBOX_API_URL") { endpoints.api = api; }
+        if let Ok(assets) = env::var("RESBOX_ASSETS_URL") { endpoints.assets = assets; }
+        if let Ok(host) = env::var("RESBOX_HUB_HOST") { endpoints.hub_host = host; }
+        if let Ok(port) = env::var("RESBOX_HUB_PORT") {
+            match port.parse() {
+                Ok(port) => endpoints.hub_port = Some(port),
+                Err(_) => println!("RESBOX_HUB_PORT isn't a port: {}", port),
+            }
+        }
+        if let Ok(path) = env::var("RESBOX_HUB_PATH") { endpoints.hub_path = path; }
+        if let Ok(unencrypted) = env::var("RESBOX_HUB_UNENCRYPTED") {
+            endpoints.hub_unencrypted = matches!(unencrypted.to_lowercase().as_str(), "1" | "true" | "yes");
+        }
+
+        // everything below assumes the bases end in a slash
+        if !endpoints.api.ends_with('/') { endpoints.api.push('/'); }
+        if !endpoints.assets.ends_with('/') { endpoints.assets.push('/'); }
+        endpoints
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        if let Ok(path) = env::var("RESBOX_ENDPOINTS_FILE") {
+            return Some(PathBuf::from(path));
+        }
+        ProjectDirs::from("com", "hedassbtw", "ResBox").map(|dirs| dirs.config_dir().join("endpoints.json"))
+    }
+
+    /// `endpoint` is relative, e.g. `users/U-Resonite`
+    pub fn api_url(&self, endpoint: &str) -> String {
+        format!("{}{}", self.api, endpoint.trim_start_matches('/'))
+    }
+
+    /// `hash` is the bit of a `resdb:///` uri before the extension
+    pub fn asset_url(&self, hash: &str) -> String {
+        format!("{}{}", self.assets, hash)
+    }
+}
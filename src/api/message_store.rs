@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+
+use super::client::{Message, Record, ResDateTime};
+
+/// Every message we know about, grouped by who the conversation's with.
+///
+/// Inserting is idempotent, so REST pages and hub pushes can both throw whatever they get at it
+/// without doubling anything up. Inserting a message with an ID we already have replaces it.
+#[derive(Default)]
+pub struct MessageStore {
+    conversations: HashMap<String, Conversation>,
+}
+
+/// One conversation, oldest first
+#[derive(Default)]
+pub struct Conversation {
+    /// keyed by send time, then ID so two messages sent in the same instant don't collide
+    ordered: BTreeMap<(DateTime<Utc>, String), Message>,
+    /// ID -> send time, to find a message's slot in `ordered` without walking it
+    send_times: HashMap<String, DateTime<Utc>>,
+    /// only messages we sent from here that the server hasn't confirmed yet, everything else is `Sent`
+    delivery: HashMap<String, DeliveryState>,
+}
+
+/// Where a message we sent is at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryState {
+    /// Shown locally, waiting on the hub's `MessageSent`
+    Pending,
+    Sent,
+    /// The hub call errored, can be retried
+    Failed,
+}
+
+impl MessageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Files the message under its `other_id`. Returns true if we didn't have it before
+    pub fn insert(&mut self, message: Message) -> bool {
+        self.conversations.entry(message.other_id.clone()).or_default().insert(message)
+    }
+
+    /// Returns how many of these were new
+    pub fn extend(&mut self, messages: impl IntoIterator<Item = Message>) -> usize {
+        messages.into_iter().map(|message| self.insert(message)).filter(|new| *new).count()
+    }
+
+    pub fn get(&self, other_id: &str) -> Option<&Conversation> {
+        self.conversations.get(other_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Conversation)> {
+        self.conversations.iter()
+    }
+
+    /// Marks messages read wherever they are, returns how many we had
+    pub fn mark_read(&mut self, ids: &[String], read_time: DateTime<Utc>) -> usize {
+        let mut found = 0;
+        for id in ids {
+            if let Some(conversation) = self.conversations.values_mut().find(|c| c.send_times.contains_key(id)) {
+                conversation.mark_read(id, read_time);
+                found += 1;
+            }
+        }
+        found
+    }
+
+    /// Marks everything they've sent us read, and hands back the IDs that changed so the server can be told
+    pub fn mark_conversation_read(&mut self, other_id: &str, read_time: DateTime<Utc>) -> Vec<String> {
+        let Some(conversation) = self.conversations.get_mut(other_id) else { return Vec::new() };
+        let ids: Vec<String> = conversation.unread().map(|message| message.id.clone()).collect();
+        for id in &ids {
+            conversation.mark_read(id, read_time);
+        }
+        ids
+    }
+
+    /// Does nothing if we don't have the message
+    pub fn set_delivery(&mut self, other_id: &str, id: &str, state: DeliveryState) {
+        if let Some(conversation) = self.conversations.get_mut(other_id) {
+            if conversation.send_times.contains_key(id) {
+                conversation.set_delivery(id, state);
+            }
+        }
+    }
+
+    /// Whatever shared item in any conversation has this `resrec:///` link
+    pub fn find_record(&self, link: &str) -> Option<Record> {
+        self.conversations.values()
+            .flat_map(|conversation| conversation.iter())
+            .filter_map(|message| message.record())
+            .find(|record| record.link() == link)
+    }
+
+    pub fn clear(&mut self) {
+        self.conversations.clear();
+    }
+}
+
+impl Conversation {
+    fn insert(&mut self, message: Message) -> bool {
+        let send_time = message.send_time.0;
+        let previous = self.send_times.insert(message.id.clone(), send_time);
+        if let Some(previous) = previous {
+            // the server can move a message around (edits, or our own send getting its real timestamp)
+            self.ordered.remove(&(previous, message.id.clone()));
+        }
+        self.ordered.insert((send_time, message.id.clone()), message);
+        previous.is_none()
+    }
+
+    fn set_delivery(&mut self, id: &str, state: DeliveryState) {
+        if state == DeliveryState::Sent {
+            self.delivery.remove(id);
+        } else {
+            self.delivery.insert(id.to_owned(), state);
+        }
+    }
+
+    pub fn delivery(&self, id: &str) -> DeliveryState {
+        self.delivery.get(id).copied().unwrap_or(DeliveryState::Sent)
+    }
+
+    fn mark_read(&mut self, id: &str, read_time: DateTime<Utc>) {
+        let Some(send_time) = self.send_times.get(id) else { return };
+        if let Some(message) = self.ordered.get_mut(&(*send_time, id.to_owned())) {
+            if message.read_time.is_none() {
+                message.read_time = Some(ResDateTime(read_time));
+            }
+        }
+    }
+
+    /// Messages from them we haven't read. Ours don't count, `read_time` on those is them reading it
+    pub fn unread(&self) -> impl Iterator<Item = &Message> {
+        self.ordered.values().filter(|message| message.sender_id == message.other_id && message.read_time.is_none())
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.unread().count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ordered.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ordered.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Message> {
+        let send_time = self.send_times.get(id)?;
+        self.ordered.get(&(*send_time, id.to_owned()))
+    }
+
+    pub fn first(&self) -> Option<&Message> {
+        self.ordered.values().next()
+    }
+
+    pub fn last(&self) -> Option<&Message> {
+        self.ordered.values().next_back()
+    }
+
+    /// Oldest to newest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Message> {
+        self.ordered.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::api::client::MessageType;
+
+    const YOU: &str = "U-you";
+    const THEM: &str = "U-them";
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    /// A text message in the conversation with `THEM`
+    fn message(id: &str, from_them: bool, secs: i64) -> Message {
+        let (sender, recipient) = if from_them { (THEM, YOU) } else { (YOU, THEM) };
+        Message {
+            id: id.to_owned(),
+            sender_id: sender.to_owned(),
+            recipient_id: recipient.to_owned(),
+            other_id: THEM.to_owned(),
+            message_type: MessageType::Text,
+            content: format!("message {}", id),
+            send_time: ResDateTime(at(secs)),
+            last_update_time: ResDateTime(at(secs)),
+            read_time: None,
+            is_migrated: true,
+            owner_id: YOU.to_owned(),
+        }
+    }
+
+    fn ids(store: &MessageStore) -> Vec<String> {
+        store.get(THEM).unwrap().iter().map(|m| m.id.clone()).collect()
+    }
+
+    #[test]
+    fn insert_is_idempotent() {
+        let mut store = MessageStore::new();
+        assert!(store.insert(message("a", true, 0)));
+        assert!(!store.insert(message("a", true, 0)));
+        assert_eq!(store.extend([message("a", true, 0), message("b", true, 1)]), 1);
+        assert_eq!(store.get(THEM).unwrap().len(), 2);
+        assert_eq!(ids(&store), ["a", "b"]);
+    }
+
+    #[test]
+    fn same_send_time_keeps_both() {
+        let mut store = MessageStore::new();
+        store.extend([message("b", true, 5), message("a", false, 5)]);
+        assert_eq!(ids(&store), ["a", "b"]);
+    }
+
+    #[test]
+    fn changed_send_time_reorders() {
+        let mut store = MessageStore::new();
+        store.extend([message("a", true, 0), message("b", false, 10), message("c", true, 20)]);
+
+        // our send getting the server's timestamp, later than what we guessed
+        let mut b = message("b", false, 30);
+        b.content = "edited".to_owned();
+        assert!(!store.insert(b));
+
+        let conversation = store.get(THEM).unwrap();
+        assert_eq!(conversation.len(), 3);
+        assert_eq!(ids(&store), ["a", "c", "b"]);
+        assert_eq!(conversation.get("b").unwrap().content, "edited");
+        assert_eq!(conversation.first().unwrap().id, "a");
+        assert_eq!(conversation.last().unwrap().id, "b");
+    }
+
+    #[test]
+    fn mark_read_finds_the_conversation() {
+        let mut store = MessageStore::new();
+        store.extend([message("a", true, 0), message("b", true, 1)]);
+        let mut elsewhere = message("x", true, 0);
+        elsewhere.other_id = "U-someone".to_owned();
+        store.insert(elsewhere);
+
+        assert_eq!(store.mark_read(&["a".to_owned(), "x".to_owned(), "nope".to_owned()], at(100)), 2);
+        assert_eq!(store.get(THEM).unwrap().get("a").unwrap().read_time, Some(ResDateTime(at(100))));
+        assert_eq!(store.get("U-someone").unwrap().get("x").unwrap().read_time, Some(ResDateTime(at(100))));
+        assert_eq!(store.get(THEM).unwrap().get("b").unwrap().read_time, None);
+
+        // already read ones keep their first read time
+        store.mark_read(&["a".to_owned()], at(200));
+        assert_eq!(store.get(THEM).unwrap().get("a").unwrap().read_time, Some(ResDateTime(at(100))));
+    }
+
+    #[test]
+    fn unread_only_counts_theirs() {
+        let mut store = MessageStore::new();
+        store.extend([message("a", true, 0), message("b", false, 1), message("c", true, 2)]);
+        assert_eq!(store.get(THEM).unwrap().unread_count(), 2);
+
+        let changed = store.mark_conversation_read(THEM, at(100));
+        assert_eq!(changed, ["a", "c"]);
+        assert_eq!(store.get(THEM).unwrap().unread_count(), 0);
+        assert!(store.mark_conversation_read(THEM, at(200)).is_empty());
+        assert!(store.mark_conversation_read("U-nobody", at(200)).is_empty());
+    }
+}
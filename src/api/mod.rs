@@ -1,2 +1,4 @@
 pub mod login;
-pub mod client;
\ No newline at end of file
+pub mod client;
+pub mod endpoints;
+pub mod message_store;
\ No newline at end of file
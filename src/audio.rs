@@ -0,0 +1,176 @@
+use std::{fs::{self, File}, io::{self, BufWriter, Write}, path::{Path, PathBuf}, sync::Arc};
+
+use directories::{ProjectDirs, UserDirs};
+use symphonia::core::{audio::SampleBuffer, codecs::{DecoderOptions, CODEC_TYPE_NULL}, errors::Error as SymphoniaError, formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint};
+
+/// How many bars the waveform gets squashed into
+pub const WAVEFORM_BARS: usize = 48;
+
+#[derive(Clone)]
+pub enum LoadableAudio {
+    /// Not asked for yet
+    Unloaded,
+    /// Downloading or decoding
+    Loading,
+    Loaded(Arc<AudioClip>),
+    /// Download failed or we can't decode it, with why
+    Failed(String),
+}
+
+/// What's left of a decoded voice message, enough for the waveform. The samples get thrown away,
+/// exporting decodes the file again instead of keeping every clip's PCM around forever
+pub struct AudioClip {
+    /// Where the original's cached on disk
+    pub path: PathBuf,
+    /// Seconds
+    pub duration: f32,
+    /// `WAVEFORM_BARS` peaks, 0.0 to 1.0, normalized so the loudest bar is full height
+    pub peaks: Vec<f32>,
+}
+
+/// Raw decoded audio
+struct Pcm {
+    sample_rate: u32,
+    channels: u16,
+    /// Interleaved, -1.0 to 1.0
+    samples: Vec<f32>,
+}
+
+impl AudioClip {
+    pub fn decode(path: &Path) -> anyhow::Result<Self> {
+        let pcm = decode_pcm(path)?;
+        let frames = pcm.samples.len() / pcm.channels.max(1) as usize;
+        let duration = frames as f32 / pcm.sample_rate.max(1) as f32;
+        let peaks = Self::peaks(&pcm.samples, pcm.channels);
+
+        Ok(Self { path: path.to_path_buf(), duration, peaks })
+    }
+
+    fn peaks(samples: &[f32], channels: u16) -> Vec<f32> {
+        let frames = samples.len() / channels.max(1) as usize;
+        if frames == 0 { return vec![0.0; WAVEFORM_BARS]; }
+
+        let per_bar = (frames as f32 / WAVEFORM_BARS as f32).max(1.0);
+        let mut peaks = vec![0.0f32; WAVEFORM_BARS];
+        for (frame, chunk) in samples.chunks(channels.max(1) as usize).enumerate() {
+            let bar = ((frame as f32 / per_bar) as usize).min(WAVEFORM_BARS - 1);
+            let loudest = chunk.iter().fold(0.0f32, |acc, s| acc.max(s.abs()));
+            peaks[bar] = peaks[bar].max(loudest);
+        }
+
+        let max = peaks.iter().cloned().fold(0.0f32, f32::max);
+        if max > 0.0 {
+            peaks.iter_mut().for_each(|p| *p /= max);
+        }
+        peaks
+    }
+
+    /// m:ss
+    pub fn duration_label(&self) -> String {
+        let secs = self.duration.round() as u32;
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+
+    /// Extension of the file as it came off the server, what `export_original` saves it as
+    pub fn original_ext(&self) -> &str {
+        self.path.extension().and_then(|e| e.to_str()).unwrap_or("ogg")
+    }
+
+    /// Copies the file as it came off the server, so whatever format it was in
+    pub fn export_original(&self, name: &str) -> io::Result<PathBuf> {
+        let target = export_dir()?.join(format!("{}.{}", name, self.original_ext()));
+        fs::copy(&self.path, &target)?;
+        Ok(target)
+    }
+
+    /// 16 bit PCM WAV, which plays everywhere. Decodes the original again since we don't keep the samples
+    pub fn export_wav(&self, name: &str) -> io::Result<PathBuf> {
+        let pcm = decode_pcm(&self.path).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        let target = export_dir()?.join(format!("{}.wav", name));
+        let mut out = BufWriter::new(File::create(&target)?);
+
+        let data_len = (pcm.samples.len() * 2) as u32;
+        let block_align = pcm.channels * 2;
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&(36 + data_len).to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&pcm.channels.to_le_bytes())?;
+        out.write_all(&pcm.sample_rate.to_le_bytes())?;
+        out.write_all(&(pcm.sample_rate * block_align as u32).to_le_bytes())?; // byte rate
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?; // bits per sample
+
+        out.write_all(b"data")?;
+        out.write_all(&data_len.to_le_bytes())?;
+        for sample in &pcm.samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            out.write_all(&sample.to_le_bytes())?;
+        }
+        out.flush()?;
+        Ok(target)
+    }
+}
+
+/// Decodes the whole file, voice messages are short enough that streaming isn't worth it
+fn decode_pcm(path: &Path) -> anyhow::Result<Pcm> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe().format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())?;
+    let mut format = probed.format;
+    let track = format.tracks().iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| anyhow::Error::msg("no audio track"))?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(48000);
+    let mut channels = track.codec_params.channels.map_or(1, |c| c.count() as u16);
+    let mut samples: Vec<f32> = Vec::new();
+    let mut buf: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // this is how symphonia says "end of file"
+            Err(SymphoniaError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id { continue; }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(err)) => { println!("skipping bad audio packet: {}", err); continue; },
+            Err(err) => return Err(err.into()),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate = spec.rate;
+        channels = spec.channels.count() as u16;
+
+        let buf = buf.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        buf.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buf.samples());
+    }
+
+    Ok(Pcm { sample_rate, channels, samples })
+}
+
+/// Downloads folder, or somewhere in our data dir if there isn't one
+fn export_dir() -> io::Result<PathBuf> {
+    let dir = UserDirs::new().and_then(|dirs| dirs.download_dir().map(|d| d.to_path_buf()))
+        .or_else(|| ProjectDirs::from("com", "hedassbtw", "ResBox").map(|dirs| dirs.data_local_dir().join("exports")))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "nowhere to save to"))?;
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
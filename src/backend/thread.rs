@@ -1,4 +1,5 @@
 use std::{collections::HashSet, future::{Future, IntoFuture}, ops::{Add, DerefMut}, str::FromStr, sync::mpsc::{Receiver, Sender}, time::{Duration, SystemTime}};
+
 use chrono::{Date, DateTime, Timelike, Utc};
 use egui::ahash::HashMap;
 use serde::{Deserialize, Serialize};
@@ -10,8 +11,12 @@ use log::info;
 use anyhow::Error;
 use uuid::Uuid;
 use lazy_static::lazy_static;
+use tokio::{sync::mpsc::{UnboundedReceiver, UnboundedSender}, time::{Instant, MissedTickBehavior}};
+
+use crate::{api::{self, message_store::DeliveryState, client::{ApiError, Client, Contact, ContactAction, LoginError, Message, MessageQuery, MESSAGE_PAGE_SIZE, ResDateTime}}, CONTACTS_LIST, ENDPOINTS, HUB_EVENTS, HUB_WAKE, MESSAGE_CACHE, SESSION_CACHE, THIS_FUCKING_SUCKS, USER_STATUSES, KEYRING_SERVICE, KEYRING_USER};
 
-use crate::{api::{self, client::{Contact, LoginError, Message, ResDateTime}}, CONTACTS_LIST, MESSAGE_CACHE, REFRESH_UI, SESSION_CACHE, THIS_FUCKING_SUCKS, USER_STATUSES};
+/// How often we ask the hub for everyone's status again
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Serialize_repr, Deserialize_repr, Clone, PartialEq)]
 #[repr(u8)]
@@ -154,7 +159,8 @@ impl UserStatus {
 }
 
 pub enum UiToReso {
-    TokenRequestCredentials(String, String, bool),
+    /// username, password, remember me, 2FA code
+    TokenRequestCredentials(String, String, bool, Option<String>),
 
     UserInfoRequest(String),
     UserStatusRequest(String),
@@ -165,6 +171,18 @@ pub enum UiToReso {
     SignalRequestStatus(Option<String>, bool),
     SignalBroadcastStatus(UserStatus, BroadcastTarget),
     SignalSendMessage(String, String),
+    /// conversation id, message id. Sends a failed message again
+    SignalRetryMessage(String, String),
+    /// who sent them, which messages. Already marked read locally, this just tells the server
+    SignalMarkMessagesRead(String, Vec<String>),
+    /// contact id, contact username, what to do
+    SignalUpdateContact(String, String, ContactAction),
+
+    /// Fetch another page of older messages with this user
+    LoadMoreMessages(String),
+
+    /// Revoke the session, drop the hub connection and clear caches
+    LogoutRequest,
 
     ShutdownRequest,
 }
@@ -172,6 +190,9 @@ pub enum ResoToUi {
     LoggedInResponse(String, String),
     LoginFailedResponse(LoginError),
     PreviousTokenInvalidResponse,
+    /// Session had to be re-established from the stored token, here's the new one
+    TokenRefreshedResponse(String),
+    LoggedOutResponse,
 
     SignalConnectedResponse,
     SignalConnectFailedResponse(signalrs_client::builder::BuilderError),
@@ -179,13 +200,21 @@ pub enum ResoToUi {
     SignalUninitialized,
 
     UserInfoResponse(String, api::client::UserInfo),
+    /// Finished loading more history for a conversation, and whether there's any more left to get
+    MessageHistoryResponse(String, bool),
+    /// Couldn't get more history, the UI holds off until it's asked again
+    MessageHistoryFailedResponse(String),
+    /// Someone sent us something over the hub, it's already in the message cache
+    MessageReceivedResponse(Message),
+    ApiRequestFailedResponse(ApiError),
 
     ThreadCrashedResponse(anyhow::Error)
 }
 
 pub struct BackendThread {
     pub rx: Receiver<ResoToUi>,
-    pub tx: Sender<UiToReso>,
+    /// unbounded so the UI never has to wait on it, the backend sleeps on the other end until something shows up
+    pub tx: UnboundedSender<UiToReso>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, HubArgument)]
@@ -249,6 +278,27 @@ pub enum InitialLoginType {
     }
 }
 
+/// What we send with `MarkMessagesRead`
+#[derive(Debug, Serialize, Deserialize, Clone, HubArgument)]
+#[serde(rename_all = "camelCase")]
+struct MarkReadBatch {
+    /// whoever sent the messages
+    sender_id: String,
+    ids: Vec<String>,
+    read_time: ResDateTime,
+}
+
+/// What `MessagesRead` tells us, keyed by who read them instead of who sent them
+#[derive(Debug, Serialize, Deserialize, Clone, HubArgument)]
+#[serde(rename_all = "camelCase")]
+struct ReadMessageBatch {
+    /// whoever read the messages, we only need the ids so a missing one's fine
+    #[serde(default)]
+    recipient_id: String,
+    ids: Vec<String>,
+    read_time: ResDateTime,
+}
+
 #[derive(Debug, Serialize, Deserialize, HubArgument)]
 struct HubArgumentValue(serde_json::Value);
 
@@ -266,7 +316,7 @@ async fn status_update(message: UserStatus) {
 
     let mut statuses = USER_STATUSES.lock();
     statuses.insert(message.user_id.clone(), message);
-    *REFRESH_UI.lock().deref_mut() = true;
+    HUB_WAKE.notify_one();
 }
 
 async fn server_log(message: String) {
@@ -277,30 +327,144 @@ async fn session_update(message: SessionUpdate) {
     let mut list = SESSION_CACHE.lock();
     let key = message.session_id.clone();
     list.insert(key, message);
-    *REFRESH_UI.lock().deref_mut() = true;
+    HUB_WAKE.notify_one();
 }
 
-async fn message_receive(message: String) {
-    println!("message received: {}", message);
-    *REFRESH_UI.lock().deref_mut() = true;
+async fn message_receive(message: Message) {
+    println!("message received from {}", message.sender_id);
+
+    if let Some(contact) = CONTACTS_LIST.lock().get_mut(&message.other_id) {
+        if contact.latest_message_time.0 < message.send_time.0 {
+            contact.latest_message_time = message.send_time.clone();
+        }
+    }
+    MESSAGE_CACHE.lock().insert(message.clone());
+    HUB_EVENTS.lock().push(ResoToUi::MessageReceivedResponse(message));
+    HUB_WAKE.notify_one();
 }
 
-async fn message_sent(message: String) {
-    println!("message sent: {}", message);
-    *REFRESH_UI.lock().deref_mut() = true;
+/// Either they read something we sent, or we read something on another device
+async fn messages_read(batch: ReadMessageBatch) {
+    let found = MESSAGE_CACHE.lock().mark_read(&batch.ids, batch.read_time.0);
+    println!("{} of {} messages read by {}", found, batch.ids.len(), batch.recipient_id);
+    HUB_WAKE.notify_one();
 }
 
+/// The server's copy of something we sent, swap it in for our local one
+async fn message_sent(message: Message) {
+    println!("message sent: {}", message.id);
+    let (other_id, id) = (message.other_id.clone(), message.id.clone());
 
+    if let Some(contact) = CONTACTS_LIST.lock().get_mut(&other_id) {
+        if contact.latest_message_time.0 < message.send_time.0 {
+            contact.latest_message_time = message.send_time.clone();
+        }
+    }
+    let mut cache = MESSAGE_CACHE.lock();
+    cache.insert(message);
+    cache.set_delivery(&other_id, &id, DeliveryState::Sent);
+    HUB_WAKE.notify_one();
+}
+
+/// Asks the hub for statuses, `None` being everyone we know
+async fn request_status(client: &Option<SignalRClient>, tx1: &Sender<ResoToUi>, id: Option<String>, invis: bool) {
+    let Some(client) = client else {
+        tx1.send(ResoToUi::SignalUninitialized).unwrap();
+        return;
+    };
+    let func = client.method("RequestStatus").arg(id);
+    let func_result = if let Ok(build) = func {
+        let build = build.arg(invis);
+        if let Ok(build) = build {
+            build.invoke_unit().await
+        } else { println!("SignalR invocation arg failed: {:?}", build.err().unwrap()); return; }
+    } else { println!("SignalR invocation build failed: {:?}", func.err().unwrap()); return; };
+    if let Err(msg) = func_result {
+        println!("SignalR invocation failed: {:?}", msg);
+        tx1.send(ResoToUi::SignalRequestFailedResponse(msg)).unwrap();
+    }
+}
+
+/// Shows the message as pending and hands it to the hub, marking it failed if that doesn't work out
+async fn send_message(client: &Option<SignalRClient>, tx1: &Sender<ResoToUi>, message: Message) {
+    let (other_id, id) = (message.other_id.clone(), message.id.clone());
+    {
+        let mut cache = MESSAGE_CACHE.lock();
+        cache.insert(message.clone());
+        cache.set_delivery(&other_id, &id, DeliveryState::Pending);
+    }
+
+    let result = if let Some(client) = client {
+        match client.method("SendMessage").arg(message) {
+            Ok(res) => res.invoke_unit().await,
+            Err(err) => Err(err),
+        }
+    } else {
+        tx1.send(ResoToUi::SignalUninitialized).unwrap();
+        MESSAGE_CACHE.lock().set_delivery(&other_id, &id, DeliveryState::Failed);
+        return;
+    };
+
+    if let Err(err) = result {
+        MESSAGE_CACHE.lock().set_delivery(&other_id, &id, DeliveryState::Failed);
+        tx1.send(ResoToUi::SignalRequestFailedResponse(err)).unwrap();
+    }
+}
+
+
+
+/// Everything we grab over REST right after logging in, failures get forwarded to the UI
+async fn fetch_initial_data(api_client: &mut Client, tx1: &Sender<ResoToUi>, your_id: &str) {
+    match api_client.get_user(your_id).await {
+        Ok(you) => tx1.send(ResoToUi::UserInfoResponse(your_id.to_owned(), you)).unwrap(),
+        Err(err) => tx1.send(ResoToUi::ApiRequestFailedResponse(err)).unwrap(),
+    }
+
+    let results = [
+        api_client.get_contacts(your_id).await,
+        api_client.get_messages(your_id, &MessageQuery::default()).await.map(|_| ()),
+        api_client.get_sessions().await,
+    ];
+    for result in results {
+        if let Err(err) = result {
+            tx1.send(ResoToUi::ApiRequestFailedResponse(err)).unwrap();
+        }
+    }
+}
+
+/// Extends the session, falling back to logging in again with the token in the keyring if the server says it's expired.
+/// Network trouble or server errors just leave things be for the next tick, only an actual rejection signs us out.
+/// Returns false if it did, so the caller can drop the hub connection too
+async fn keep_session_alive(api_client: &mut Client, tx1: &Sender<ResoToUi>) -> bool {
+    match api_client.extend_session().await {
+        Ok(()) => { println!("session extended, expires {:?}", api_client.expires_at()); return true; },
+        Err(err @ ApiError::AuthExpired { .. }) => println!("session extension rejected: {}", err),
+        Err(err) => { println!("session extension failed, trying again later: {}", err); return true; },
+    }
+
+    let stored_token = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).and_then(|entry| entry.get_password());
+    if let Ok(session_token) = stored_token {
+        match api_client.reauthenticate(session_token).await {
+            Ok(token) => { tx1.send(ResoToUi::TokenRefreshedResponse(token)).unwrap(); return true; },
+            // couldn't even ask, the token might still be good
+            Err(LoginError::RequestFailed) => { println!("re-authentication couldn't reach the server, trying again later"); return true; },
+            Err(err) => println!("re-authentication failed: {}", err),
+        }
+    }
+
+    api_client.clear_session();
+    tx1.send(ResoToUi::PreviousTokenInvalidResponse).unwrap();
+    false
+}
 
 impl BackendThread {
     pub fn new(ctx: &egui::Context, creds: InitialLoginType) -> Self {
-        let (tx0, rx1) = std::sync::mpsc::channel();
+        let (tx0, rx1) = tokio::sync::mpsc::unbounded_channel();
         let (tx1, rx0) = std::sync::mpsc::channel();
         let context = ctx.clone();
-        let tx00 = tx0.clone();
         tokio::task::spawn(async move {
             let tx11 = tx1.clone();
-            let result = BackendThread::run(rx1, tx00, tx1, &context, creds).await;
+            let result = BackendThread::run(rx1, tx1, &context, creds).await;
             if let Err(res) = result {
                 tx11.send(ResoToUi::ThreadCrashedResponse(res)).unwrap();
             }
@@ -310,8 +474,7 @@ impl BackendThread {
     }
 
     async fn run(
-        rx1: Receiver<UiToReso>,
-        tx0: Sender<UiToReso>,
+        mut rx1: UnboundedReceiver<UiToReso>,
         tx1: Sender<ResoToUi>,
         ctx: &egui::Context,
         creds: InitialLoginType
@@ -323,19 +486,11 @@ impl BackendThread {
             InitialLoginType::PreviousToken { username, session_token } => {
                 let api_login = api_client.login(&username, api::client::UserSessionsAuthReq::Token {
                     _type: "sessionToken".to_owned(), session_token
-                }, true).await; // assume remember me, because why not tbh
+                }, true, None).await; // assume remember me, because why not tbh
                 if let core::result::Result::Ok(token) = api_login {
                     let your_id = api_client.user_id.clone().unwrap();
                     tx1.send(ResoToUi::LoggedInResponse(token, your_id.clone())).unwrap();
-                    if let Ok(you) = api_client.get_user(&your_id.clone()).await {
-                        tx1.send(ResoToUi::UserInfoResponse(your_id.clone(), you)).unwrap();
-                        
-                        api_client.get_contacts(&your_id.clone()).await;
-                        api_client.get_messages(&your_id.clone()).await;
-                        api_client.get_sessions().await;
-                    } else {
-                        println!("uh? whoops?");
-                    }
+                    fetch_initial_data(&mut api_client, &tx1, &your_id).await;
                 } else {
                     println!("previous tokens invalid, boowomp {:?}", api_login.err().unwrap());
                     tx1.send(ResoToUi::PreviousTokenInvalidResponse).unwrap();
@@ -347,42 +502,48 @@ impl BackendThread {
         }
         ctx.request_repaint();
        
-        let mut future  = SystemTime::now();
-        future = future.checked_add(Duration::from_secs(10)).unwrap();
+        // first one's 10 seconds out, same as before, and a slow tick doesn't make us fire a bunch to catch up
+        let mut status_interval = tokio::time::interval_at(Instant::now() + STATUS_INTERVAL, STATUS_INTERVAL);
+        status_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         'outer: loop {
-            let now = SystemTime::now();
-            if now >= future {
-                future = now.checked_add(Duration::from_secs(10)).unwrap();
-                tx0.send(UiToReso::SignalRequestStatus(None, false)).unwrap(); // this sucks!
-            }
-            {
-                let mut boolin = REFRESH_UI.lock();
-                if *boolin {
+            // sleeps until one of these has something, instead of spinning a core on try_recv
+            let request = tokio::select! {
+                request = rx1.recv() => match request {
+                    Some(request) => request,
+                    None => break 'outer Ok(()), // the UI's gone, nobody left to talk to
+                },
+                _ = status_interval.tick() => {
+                    if client.is_some() {
+                        request_status(&client, &tx1, None, false).await;
+                    }
+                    if api_client.needs_extension() {
+                        if !keep_session_alive(&mut api_client, &tx1).await {
+                            // same as logging out, the hub shouldn't keep feeding us stuff for an account we're not signed in to
+                            client = None;
+                        }
+                        ctx.request_repaint();
+                    }
+                    continue;
+                },
+                _ = HUB_WAKE.notified() => {
+                    for event in HUB_EVENTS.lock().drain(..) {
+                        tx1.send(event).unwrap();
+                    }
                     ctx.request_repaint();
-                    *boolin = false;
-                }
-            }
-            let request = rx1.try_recv();
-            if request.is_err() {
-                continue;
-            }
+                    continue;
+                },
+            };
 
-            match request? {
-                UiToReso::TokenRequestCredentials(username, pass, remember) => {
+            match request {
+                UiToReso::TokenRequestCredentials(username, pass, remember, totp) => {
                     let api_login = api_client.login(&username, api::client::UserSessionsAuthReq::Credentials {
                         _type: "password".to_owned(), password: pass
-                    }, remember).await;
+                    }, remember, totp.as_deref()).await;
                     if let core::result::Result::Ok(token) = api_login {
                         let your_id = api_client.user_id.clone().unwrap();
                         tx1.send(ResoToUi::LoggedInResponse(token, your_id.clone())).unwrap();
-                        if let Ok(you) = api_client.get_user(&your_id.clone()).await {
-                            tx1.send(ResoToUi::UserInfoResponse(your_id.clone(), you)).unwrap();
-                            api_client.get_contacts(&your_id.clone()).await;
-                            api_client.get_messages(&your_id.clone()).await;
-                        } else {
-                            println!("uh? whoops?");
-                        }
+                        fetch_initial_data(&mut api_client, &tx1, &your_id).await;
                     } else {
                         tx1.send(ResoToUi::LoginFailedResponse(api_login.err().unwrap())).unwrap();
                     }
@@ -394,15 +555,21 @@ impl BackendThread {
                     .method("Debug", server_log)    
                     .method("ReceiveMessage", message_receive)
                     .method("MessageSent", message_sent)
+                    .method("MessagesRead", messages_read)
                     .method("ReceiveSessionUpdate", session_update)
                     ;
 
-                    let result = SignalRClient::builder("api.resonite.com")
-                    .use_hub("hub")
+                    let mut builder = SignalRClient::builder(&ENDPOINTS.hub_host)
+                    .use_hub(&ENDPOINTS.hub_path)
                     .with_client_hub(hub)
-                    .use_authentication(signalrs_client::builder::Auth::Resonite { uid: api_client.hwid.clone(), id, token })
-                    //.use_unencrypted_connection()
-                    .build().await;
+                    .use_authentication(signalrs_client::builder::Auth::Resonite { uid: api_client.hwid.clone(), id, token });
+                    if let Some(port) = ENDPOINTS.hub_port {
+                        builder = builder.use_port(port.into()); // signalrs wants a usize
+                    }
+                    if ENDPOINTS.hub_unencrypted {
+                        builder = builder.use_unencrypted_connection();
+                    }
+                    let result = builder.build().await;
                     if let core::result::Result::Ok(r_client) = result {
                         client = Some(r_client);
                         tx1.send(ResoToUi::SignalConnectedResponse).unwrap();
@@ -423,23 +590,7 @@ impl BackendThread {
                         }
                     } else { tx1.send(ResoToUi::SignalUninitialized).unwrap(); }
                 },
-                UiToReso::SignalRequestStatus(id, invis) => {
-                    if let Some(client) = &client {
-                        let func = client.method("RequestStatus").arg(id);
-                        let func_result = if let Ok(build) = func {
-                            let build = build.arg(invis);
-                            if let Ok(build) = build {
-                                build.invoke_unit().await
-                            } else { println!("SignalR invocation arg failed: {:?}", build.err().unwrap()); continue; }
-                        } else { println!("SignalR invocation build failed: {:?}", func.err().unwrap()); continue; };
-                        if let Err(msg) = func_result {
-                            println!("SignalR invocation failed: {:?}", msg);
-                            tx1.send(ResoToUi::SignalRequestFailedResponse(msg)).unwrap();
-                        } else {
-                            //println!("guh");
-                        }
-                    } else { tx1.send(ResoToUi::SignalUninitialized).unwrap(); }
-                },
+                UiToReso::SignalRequestStatus(id, invis) => request_status(&client, &tx1, id, invis).await,
                 UiToReso::SignalBroadcastStatus(a, b) => {
                     // cache it for ourselfs first
                     { USER_STATUSES.lock().insert(a.user_id.clone(), a.clone()); }
@@ -456,42 +607,35 @@ impl BackendThread {
                     } else { tx1.send(ResoToUi::SignalUninitialized).unwrap(); }
                 },
                 UiToReso::UserInfoRequest(uid) => {
-                    let uinfo = api_client.get_users(&uid.clone()).await;
-                    if let Ok(user) = uinfo {
-                        for user in user {
+                    match api_client.get_users(&uid.clone()).await {
+                        Ok(users) => for user in users {
                             tx1.send(ResoToUi::UserInfoResponse(user.id.clone(), user)).unwrap();
-                        }
-                        ctx.request_repaint();
-                    } else {
-                        println!("{:?}",uinfo.err().unwrap());
+                        },
+                        Err(err) => tx1.send(ResoToUi::ApiRequestFailedResponse(err)).unwrap(),
                     }
+                    ctx.request_repaint();
                 },
                 UiToReso::SignalSendMessage(uid, content) => {
-                    let now = SystemTime::now();
-                    let now: DateTime<Utc> = now.into();
-                    let now = ResDateTime(now);
-                    let send = Message {
-                        id: String::new(),
-                        sender_id: api_client.user_id.clone().unwrap(),
-                        recipient_id: uid.clone(),
-                        other_id: uid.clone(),
-                        message_type: api::client::MessageType::Text,
-                        content,
-                        send_time: now.clone(),
-                        last_update_time: now,
-                        read_time: None,
-                        is_migrated: true,
-                        owner_id: api_client.user_id.clone().unwrap(),
-                    };
-
+                    let message = Message::new_text(api_client.user_id.as_deref().unwrap_or_default(), &uid, content);
+                    send_message(&client, &tx1, message).await;
+                    ctx.request_repaint();
+                },
+                UiToReso::SignalRetryMessage(other_id, id) => {
+                    let message = MESSAGE_CACHE.lock().get(&other_id).and_then(|c| c.get(&id)).cloned();
+                    if let Some(message) = message {
+                        send_message(&client, &tx1, message).await;
+                    }
+                    ctx.request_repaint();
+                },
+                UiToReso::SignalMarkMessagesRead(sender_id, ids) => {
                     if let Some(client) = &client {
-                        let res = client.method("SendMessage").arg(send);
-                        if let Ok(res) = res {
-                            if let Err(msg) = res.invoke_unit().await {
-                                tx1.send(ResoToUi::SignalRequestFailedResponse(msg)).unwrap();
-                            }
-                        } else if let Err(msg) = res {
-                            tx1.send(ResoToUi::SignalRequestFailedResponse(msg)).unwrap();
+                        let batch = MarkReadBatch { sender_id, ids, read_time: ResDateTime(Utc::now()) };
+                        let result = match client.method("MarkMessagesRead").arg(batch) {
+                            Ok(res) => res.invoke_unit().await,
+                            Err(err) => Err(err),
+                        };
+                        if let Err(err) = result {
+                            tx1.send(ResoToUi::SignalRequestFailedResponse(err)).unwrap();
                         }
                     } else { tx1.send(ResoToUi::SignalUninitialized).unwrap(); }
                 },
@@ -508,7 +652,69 @@ impl BackendThread {
                     } else { tx1.send(ResoToUi::SignalUninitialized).unwrap(); }
                 },
                 UiToReso::UserStatusRequest(id) => {
-                    api_client.get_status(&id).await;
+                    match api_client.get_status(&id).await {
+                        Ok(_) => ctx.request_repaint(),
+                        Err(err) => tx1.send(ResoToUi::ApiRequestFailedResponse(err)).unwrap(),
+                    }
+                },
+                UiToReso::SignalUpdateContact(id, username, action) => {
+                    let previous = CONTACTS_LIST.lock().get(&id).cloned();
+                    let updated = match api_client.contact_update(previous.as_ref(), &id, &username, action) {
+                        Ok(updated) => updated,
+                        Err(err) => { tx1.send(ResoToUi::ApiRequestFailedResponse(err)).unwrap(); continue; },
+                    };
+
+                    if let Some(client) = &client {
+                        // optimistic, rolled back below if the hub says no
+                        CONTACTS_LIST.lock().insert(id.clone(), updated.clone());
+                        ctx.request_repaint();
+
+                        let res = match client.method("UpdateContact").arg(updated) {
+                            Ok(res) => res.invoke_unit().await,
+                            Err(msg) => Err(msg),
+                        };
+                        if let Err(msg) = res {
+                            {
+                                let mut list = CONTACTS_LIST.lock();
+                                match previous {
+                                    Some(previous) => { list.insert(id, previous); },
+                                    None => { list.remove(&id); },
+                                }
+                            }
+                            tx1.send(ResoToUi::SignalRequestFailedResponse(msg)).unwrap();
+                        }
+                        ctx.request_repaint();
+                    } else { tx1.send(ResoToUi::SignalUninitialized).unwrap(); }
+                },
+                UiToReso::LoadMoreMessages(other_id) => {
+                    let your_id = if let Some(id) = api_client.user_id.clone() { id } else { tx1.send(ResoToUi::MessageHistoryFailedResponse(other_id)).unwrap(); continue; };
+                    let oldest = || MESSAGE_CACHE.lock().get(&other_id).and_then(|msgs| msgs.first().map(|msg| msg.id.clone()));
+                    let oldest_before = oldest();
+                    let have = MESSAGE_CACHE.lock().get(&other_id).map_or(0, |msgs| msgs.len()) as u32;
+                    // the endpoint only hands back the newest maxItems and only has a "newer than" filter, no "older than",
+                    // so going further back means asking for more. the UI only asks once per scroll to the top so it doesn't run away
+                    let wanted = have + MESSAGE_PAGE_SIZE;
+                    match api_client.get_messages(&your_id, &MessageQuery::conversation(&other_id, wanted)).await {
+                        Ok(count) => {
+                            // a short page is the end, and so is a full one that didn't get us anything older (server capping maxItems)
+                            let exhausted = (count as u32) < wanted || oldest() == oldest_before;
+                            tx1.send(ResoToUi::MessageHistoryResponse(other_id, exhausted)).unwrap();
+                        },
+                        Err(err) => {
+                            tx1.send(ResoToUi::ApiRequestFailedResponse(err)).unwrap();
+                            tx1.send(ResoToUi::MessageHistoryFailedResponse(other_id)).unwrap();
+                        },
+                    }
+                    ctx.request_repaint();
+                },
+                UiToReso::LogoutRequest => {
+                    // dropping the client closes the connection
+                    client = None;
+                    if let Err(err) = api_client.logout().await {
+                        tx1.send(ResoToUi::ApiRequestFailedResponse(err)).unwrap();
+                    }
+                    tx1.send(ResoToUi::LoggedOutResponse).unwrap();
+                    ctx.request_repaint();
                 },
                 UiToReso::ShutdownRequest => break 'outer Ok(()),
             }
@@ -4,6 +4,8 @@ pub fn logged_in(app: &mut TemplateApp, token: String, user_id: String) {
     app.token = token.clone();
     app.user_id = Some(user_id.clone());
     app.logged_in = true;
+    app.entry_fields.login_details.needs_totp = false;
+    app.entry_fields.login_details.totp.clear();
     app.backend.tx.send(backend::thread::UiToReso::SignalConnectRequest(user_id.clone(), app.token.clone())).unwrap();
     //app.notifications.push(icon_notification("", "SignalR Status Disabled", "SignalInitializeStatus not sent"));
     app.backend.tx.send(backend::thread::UiToReso::SignalRequestStatus(None, false)).unwrap(); // might be polling? idk?
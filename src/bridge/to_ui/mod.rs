@@ -1,4 +1,4 @@
-use crate::{backend::{self, thread::ResoToUi}, icon_notification, FrontendNotification, FrontendNotificationIcon, FrontendPage, TemplateApp};
+use crate::{api::client::{ApiError, LoginError}, backend::{self, thread::ResoToUi}, icon_notification, FrontendNotification, FrontendNotificationIcon, FrontendPage, TemplateApp, CONTACTS_LIST, KEYRING_SERVICE, KEYRING_USER};
 
 // i don't like doing one function per file, but some of these functions are really long, it's somewhat justified
 
@@ -9,14 +9,31 @@ pub fn process_to_ui(app: &mut TemplateApp, variant: ResoToUi) {
     match variant {
         backend::thread::ResoToUi::LoggedInResponse(token, uid) => logged_in(app, token, uid),
         backend::thread::ResoToUi::LoginFailedResponse(reason) => {
-            app.notifications.push(icon_notification("", "Login failed", &format!("{}", reason)));
+            match reason {
+                LoginError::TotpRequired => {
+                    // not really a failure, just the first half of a 2FA login
+                    app.entry_fields.login_details.needs_totp = true;
+                },
+                LoginError::InvalidTotp => {
+                    app.entry_fields.login_details.totp.clear();
+                    app.notifications.push(icon_notification("", "Login failed", &format!("{}", reason)));
+                },
+                _ => {
+                    app.entry_fields.login_details.needs_totp = false;
+                    app.entry_fields.login_details.totp.clear();
+                    app.notifications.push(icon_notification("", "Login failed", &format!("{}", reason)));
+                },
+            }
+            if app.current_page() == &FrontendPage::LoadingPage {
+                app.set_page(FrontendPage::SignInPage);
+            }
             app.can_attempt_login = true;
         }
         backend::thread::ResoToUi::UserInfoResponse(id, user) => {
             if app.is_you(&id) {
                 app.you = Some(user.clone());
                 if let Some(profile) = &user.profile {
-                    app.notifications.push(FrontendNotification { icon: FrontendNotificationIcon::LoadableImage(app.image_cache.get_image(&profile.icon_url)), text: format!("Hi {}!", &user.username), sub: "You're signed in".to_owned() });
+                    app.notifications.push(FrontendNotification { icon: FrontendNotificationIcon::LoadableImage(app.image_cache.get_image(&profile.icon_url)), text: format!("Hi {}!", &user.username), sub: "You're signed in".to_owned(), action: None });
                 } else {
                     app.notifications.push(icon_notification("", &format!("Hi {}!", &user.username), "You're signed in"));
                 }
@@ -27,6 +44,42 @@ pub fn process_to_ui(app: &mut TemplateApp, variant: ResoToUi) {
                 app.entry_fields.user_info_query_results.push(id);
             }
         }
+        backend::thread::ResoToUi::MessageHistoryResponse(id, exhausted) => {
+            app.history_loading.remove(&id);
+            // wait for them to scroll away and back before getting another page
+            app.history_hold.insert(id.clone());
+            if exhausted {
+                app.history_exhausted.insert(id);
+            }
+        }
+        backend::thread::ResoToUi::MessageHistoryFailedResponse(id) => {
+            app.history_loading.remove(&id);
+            app.history_anchor.remove(&id);
+            app.history_failed.insert(id);
+        }
+        backend::thread::ResoToUi::MessageReceivedResponse(message) => {
+            let page = FrontendPage::ConversationPage(message.other_id.clone());
+            // no point telling you about the conversation you're looking at
+            if app.is_you(&message.sender_id) || app.current_page() == &page { return; }
+
+            let contact = CONTACTS_LIST.lock().get(&message.sender_id).cloned();
+            let name = contact.as_ref().map_or(message.sender_id.clone(), |c| c.contact_username.clone());
+            let icon = match contact.as_ref().and_then(|c| c.profile.as_ref()) {
+                Some(profile) => FrontendNotificationIcon::LoadableImage(app.image_cache.get_image(&profile.icon_url)),
+                None => FrontendNotificationIcon::SegoeIcon("".to_owned()),
+            };
+            app.notifications.push(FrontendNotification { icon, text: name, sub: message.preview(), action: Some(page) });
+        }
+        backend::thread::ResoToUi::ApiRequestFailedResponse(err) => {
+            log::error!("API error: {}", err);
+            let sub = match &err {
+                ApiError::Status { status, .. } => format!("{}", status),
+                ApiError::AuthExpired { .. } => "Try signing in again".to_owned(),
+                ApiError::NoResults { .. } if app.current_page() == &FrontendPage::UserSearchPage => "Nobody by that name".to_owned(),
+                _ => format!("{}", err),
+            };
+            app.notifications.push(icon_notification("", err.summary(), &sub));
+        }
         backend::thread::ResoToUi::SignalConnectFailedResponse(err) => {
             app.notifications.push(FrontendNotification {
                 icon: FrontendNotificationIcon::SegoeIcon("".to_owned()),
@@ -46,7 +99,8 @@ pub fn process_to_ui(app: &mut TemplateApp, variant: ResoToUi) {
                     signalrs_client::builder::BuilderError::Transport { source } => {
                         format!("Transport error: {}", source)
                     },
-                })
+                }),
+                action: None,
             });
         }
         backend::thread::ResoToUi::SignalRequestFailedResponse(stat) => {
@@ -67,7 +121,8 @@ pub fn process_to_ui(app: &mut TemplateApp, variant: ResoToUi) {
                         signalrs_client::error::ClientError::Result { message } => { println!("server error: {}", message); "Server error" },
                         signalrs_client::error::ClientError::TransportInavailable { message } => { "Cannot reach transport" },
                         signalrs_client::error::ClientError::Handshake { message } => { println!("{}", message); "Handshake" },
-                    })
+                    }),
+                    action: None,
                 });
         }
         backend::thread::ResoToUi::SignalConnectedResponse => app.notifications.push(icon_notification("", "SignalR Connected!", "")),
@@ -76,7 +131,32 @@ pub fn process_to_ui(app: &mut TemplateApp, variant: ResoToUi) {
             //  exclamation mark
             app.notifications.push(icon_notification("", "Backend Crashed", &format!("{}", err)));
         }
+        backend::thread::ResoToUi::LoggedOutResponse => {
+            app.reset_session();
+            app.notifications.push(icon_notification("", "Signed out", ""));
+        }
+        backend::thread::ResoToUi::TokenRefreshedResponse(token) => {
+            app.token = token;
+            if let Some(user_id) = app.user_id.clone() {
+                // the hub authenticated with the old token, reconnect with the new one
+                app.backend.tx.send(backend::thread::UiToReso::SignalConnectRequest(user_id, app.token.clone())).unwrap();
+                app.backend.tx.send(backend::thread::UiToReso::SignalInitializeStatus).unwrap();
+            }
+            if app.entry_fields.login_details.remember_me {
+                if let Ok(entry) = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER) {
+                    if let Err(err) = entry.set_password(&app.token) {
+                        app.notifications.push(icon_notification("", "Keyring Failed", &format!("{}", err)));
+                    }
+                }
+            }
+        }
         backend::thread::ResoToUi::PreviousTokenInvalidResponse => {
+            if app.logged_in {
+                // we were signed in, and now we can't be
+                app.reset_session();
+                app.notifications.push(icon_notification("", "Session expired", "Sign in again to continue"));
+                return;
+            }
             if app.current_page() == &FrontendPage::LoadingPage {
                 app.set_page(FrontendPage::SignInPage);
             }
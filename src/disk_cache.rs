@@ -0,0 +1,155 @@
+use std::{collections::HashMap, fs, io, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use serde::{Deserialize, Serialize};
+
+/// Where the index lives, inside the cache folder itself
+const INDEX_FILE: &str = "index.json";
+/// Anything nobody's looked at in this long gets deleted at startup, even if we're under the limit
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+/// The limits settings lets you pick between, in MB
+pub const SIZE_LIMITS_MB: [u64; 5] = [128, 256, 512, 1024, 2048];
+pub const DEFAULT_LIMIT_MB: u64 = 512;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Entry {
+    size: u64,
+    /// unix seconds
+    last_access: u64,
+}
+
+/// Keeps track of what's in a cache folder so it can be kept under a size, dropping whatever was used least recently first.
+/// Only gets saved on the way out, if we crash the startup sweep picks up anything it missed from the files themselves
+pub struct DiskCache {
+    dir: PathBuf,
+    /// file name -> size and when it was last used
+    entries: HashMap<String, Entry>,
+    total: u64,
+    max_size: u64,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl DiskCache {
+    /// Loads the index and sweeps the folder: forgets files that are gone, picks up ones that aren't indexed,
+    /// deletes stale and half written ones, then evicts down to `max_size`
+    pub fn open(dir: &Path, max_size: u64) -> Self {
+        let mut entries: HashMap<String, Entry> = fs::read_to_string(dir.join(INDEX_FILE)).ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        let mut on_disk = HashMap::new();
+        if let Ok(read_dir) = fs::read_dir(dir) {
+            for file in read_dir.flatten() {
+                let Ok(meta) = file.metadata() else { continue };
+                if !meta.is_file() { continue; }
+                let name = file.file_name().to_string_lossy().to_string();
+                if name == INDEX_FILE { continue; }
+                if name.ends_with(".tmp") {
+                    // a download that never finished
+                    let _ = fs::remove_file(file.path());
+                    continue;
+                }
+                let modified = meta.modified().ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs());
+                on_disk.insert(name, Entry { size: meta.len(), last_access: modified });
+            }
+        }
+
+        // the files themselves are the truth, the index just remembers access times
+        entries.retain(|name, _| on_disk.contains_key(name));
+        for (name, file) in on_disk {
+            let entry = entries.entry(name).or_insert_with(|| file.clone());
+            entry.size = file.size;
+        }
+
+        let mut cache = Self { dir: dir.to_path_buf(), total: 0, entries, max_size };
+        let stale_before = now_secs().saturating_sub(STALE_AFTER.as_secs());
+        let stale: Vec<String> = cache.entries.iter()
+            .filter(|(_, entry)| entry.last_access < stale_before)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stale {
+            cache.remove(&name);
+        }
+        cache.total = cache.entries.values().map(|entry| entry.size).sum();
+        cache.evict(None);
+        println!("image cache: {} files, {} bytes", cache.entries.len(), cache.total);
+        cache
+    }
+
+    /// Something got read, bump it to the back of the eviction line
+    pub fn touch(&mut self, name: &str) {
+        if let Some(entry) = self.entries.get_mut(name) {
+            entry.last_access = now_secs();
+        }
+    }
+
+    /// A new file got written, evicts older stuff if that put us over
+    pub fn insert(&mut self, name: &str, size: u64) {
+        let previous = self.entries.insert(name.to_owned(), Entry { size, last_access: now_secs() });
+        self.total = self.total - previous.map_or(0, |entry| entry.size) + size;
+        self.evict(Some(name));
+    }
+
+    /// Deletes the file and forgets it
+    pub fn remove(&mut self, name: &str) {
+        if let Some(entry) = self.entries.remove(name) {
+            self.total = self.total.saturating_sub(entry.size);
+        }
+        if let Err(err) = fs::remove_file(self.dir.join(name)) {
+            if err.kind() != io::ErrorKind::NotFound {
+                println!("couldn't delete cached {}: {:?}", name, err);
+            }
+        }
+    }
+
+    /// Least recently used first until we fit. Never throws out `keep`, the file that just got written, even if it's
+    /// bigger than the limit on its own. Access times are only to the second so it can't just be "the newest one"
+    fn evict(&mut self, keep: Option<&str>) {
+        if self.total <= self.max_size { return; }
+        let mut by_age: Vec<(String, u64)> = self.entries.iter()
+            .filter(|(name, _)| Some(name.as_str()) != keep)
+            .map(|(name, entry)| (name.clone(), entry.last_access))
+            .collect();
+        by_age.sort_by_key(|(_, last_access)| *last_access);
+
+        for (name, _) in &by_age {
+            if self.total <= self.max_size { break; }
+            self.remove(name);
+        }
+    }
+
+    pub fn usage(&self) -> u64 {
+        self.total
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+        self.evict(None);
+    }
+
+    /// Deletes everything
+    pub fn clear(&mut self) {
+        let names: Vec<String> = self.entries.keys().cloned().collect();
+        for name in names {
+            self.remove(&name);
+        }
+        self.total = 0;
+        self.save();
+    }
+
+    pub fn save(&self) {
+        let result = serde_json::to_string(&self.entries).map_err(io::Error::from)
+            .and_then(|json| fs::write(self.dir.join(INDEX_FILE), json));
+        if let Err(err) = result {
+            println!("couldn't save image cache index: {:?}", err);
+        }
+    }
+}
@@ -1,8 +1,11 @@
-use std::{collections::HashMap, f32::consts::E, fs::{self, File}, io::{self, Write}, path::PathBuf, sync::{mpsc::{Receiver, Sender}, Arc, Mutex}};
+use std::{collections::{HashMap, HashSet}, f32::consts::E, fs::{self, File}, io::{self, Write}, path::PathBuf, sync::{mpsc::{Receiver, Sender}, Arc, Mutex}, time::{Duration, Instant}};
+
+use tokio::{sync::{mpsc::{UnboundedReceiver, UnboundedSender}, Semaphore}, task::JoinSet};
 
 use image::{io::Reader as ImageReader, DynamicImage};
 
 use directories::ProjectDirs;
+use crate::{audio::{AudioClip, LoadableAudio}, disk_cache::DiskCache};
 use egui::{ColorImage, TextureHandle, TextureId, TextureOptions};
 
 
@@ -12,113 +15,309 @@ pub enum LoadableImage {
     /// In progress
     Loading,
     /// Here ya go
-    Loaded(TextureId)
+    Loaded(TextureId),
+    /// Download or decode didn't work out, with why. It'll try again by itself after a while, or sooner with the handle
+    Failed(String, ImageRetry),
+}
+
+/// Lets whoever's drawing a failed image skip the wait before the next attempt
+#[derive(Clone)]
+pub struct ImageRetry {
+    uri: String,
+    failed: Arc<Mutex<HashMap<String, FailedLoad>>>,
+}
+
+impl ImageRetry {
+    /// Next `get_image` for it goes straight back to the loader
+    pub fn now(&self) {
+        if let Some(failed) = self.failed.lock().unwrap().get_mut(&self.uri) {
+            failed.retry_at = Instant::now();
+        }
+    }
+}
+
+/// Why an image failed and when we're allowed to try it again
+struct FailedLoad {
+    reason: String,
+    /// failures in a row, each one doubles the wait
+    attempts: u32,
+    retry_at: Instant,
 }
 
+/// How many downloads can be going at once, the rest wait their turn
+const MAX_DOWNLOADS: usize = 6;
+/// If nothing's drawn an image for this many frames by the time its turn comes up, it scrolled away and we skip it
+const CANCEL_AFTER_FRAMES: u64 = 30;
+/// Wait after the first failure, doubled every time after that up to `RETRY_MAX`
+const RETRY_BASE: Duration = Duration::from_secs(2);
+const RETRY_MAX: Duration = Duration::from_secs(300);
+
 enum LoaderRequest {
     Shutdown,
-    GetImg(String)
+    GetImg(String),
+    GetAudio(String),
 }
 
 pub struct ResDbImageCache {
     cache_path: Option<PathBuf>,
+    /// what's in `cache_path` and how big it is, `None` along with it
+    disk: Option<Arc<Mutex<DiskCache>>>,
     db: Arc<Mutex<HashMap<String, Option<TextureHandle>>>>,
+    /// voice messages, they come from the same place so they go through the same loader
+    audio: Arc<Mutex<HashMap<String, LoadableAudio>>>,
+    /// frame each still-loading image was last asked for, so the loader can tell what's gone offscreen
+    wanted: Arc<Mutex<HashMap<String, u64>>>,
+    /// images that didn't load, kept out of `db` so they're not stuck as loading forever
+    failed: Arc<Mutex<HashMap<String, FailedLoad>>>,
 
-    tx: Sender<LoaderRequest>,
+    tx: UnboundedSender<LoaderRequest>,
+
+    ctx: egui::Context,
+}
 
+/// Everything one download task needs, cheap to clone
+#[derive(Clone)]
+struct LoaderShared {
+    client: reqwest::Client,
+    cache: PathBuf,
+    disk: Arc<Mutex<DiskCache>>,
+    map: Arc<Mutex<HashMap<String, Option<TextureHandle>>>>,
+    audio: Arc<Mutex<HashMap<String, LoadableAudio>>>,
+    wanted: Arc<Mutex<HashMap<String, u64>>>,
+    failed: Arc<Mutex<HashMap<String, FailedLoad>>>,
+    /// uris somebody's already working on, so asking twice doesn't download twice
+    in_flight: Arc<Mutex<HashSet<String>>>,
+    downloads: Arc<Semaphore>,
     ctx: egui::Context,
 }
 
 impl ResDbImageCache {
-    pub fn new(ctx: egui::Context) -> Self {
-        let (tx0, rx1) = std::sync::mpsc::channel();
+    /// `max_size` is in bytes, for what's on disk, not what's loaded
+    pub fn new(ctx: egui::Context, max_size: u64) -> Self {
+        let (tx0, rx1) = tokio::sync::mpsc::unbounded_channel();
+        let wanted = Arc::new(Mutex::new(HashMap::new()));
+        let failed = Arc::new(Mutex::new(HashMap::new()));
 
         let proj_dirs = ProjectDirs::from("com", "hedassbtw",  "ResBox");
-        if proj_dirs.is_none() { println!("Could not create image cache folder"); return Self { cache_path: None, db: Arc::new(Mutex::new(HashMap::new())), tx: tx0, ctx}; }
+        if proj_dirs.is_none() { println!("Could not create image cache folder"); return Self { cache_path: None, disk: None, db: Arc::new(Mutex::new(HashMap::new())), audio: Arc::new(Mutex::new(HashMap::new())), wanted, failed, tx: tx0, ctx}; }
         let proj_dirs = proj_dirs.unwrap();
         
         let dir = proj_dirs.data_local_dir().join("image_cache");
         if !dir.exists() { fs::create_dir_all(&dir).unwrap(); }
         
         let cache = dir.clone();
+        let disk = Arc::new(Mutex::new(DiskCache::open(&dir, max_size)));
+        let disk0 = disk.clone();
         
         let map: Arc<Mutex<HashMap<String, Option<TextureHandle>>>> = Arc::new(Mutex::new(HashMap::new()));
         let map0 = map.clone();
+        let audio: Arc<Mutex<HashMap<String, LoadableAudio>>> = Arc::new(Mutex::new(HashMap::new()));
+        let audio0 = audio.clone();
+        let wanted0 = wanted.clone();
+        let failed0 = failed.clone();
         let ctx0 = ctx.clone();
         
         tokio::task::spawn(async move {
-            let _result = ResDbImageCache::run(rx1, map0, cache, ctx0).await;
+            let result = ResDbImageCache::run(rx1, map0, audio0, wanted0, failed0, cache, disk0, ctx0).await;
+            if let Err(err) = result {
+                println!("image loader died: {:?}", err);
+            }
         });
 
-        Self { cache_path: Some(dir), db: map, tx: tx0, ctx}
+        Self { cache_path: Some(dir), disk: Some(disk), db: map, audio, wanted, failed, tx: tx0, ctx}
     }
     
+    /// Hands each request to its own task, `MAX_DOWNLOADS` of them at a time, and waits for the next one
     async fn run(
-        rx1: Receiver<LoaderRequest>,
+        mut rx1: UnboundedReceiver<LoaderRequest>,
         map: Arc<Mutex<HashMap<String, Option<TextureHandle>>>>,
+        audio: Arc<Mutex<HashMap<String, LoadableAudio>>>,
+        wanted: Arc<Mutex<HashMap<String, u64>>>,
+        failed: Arc<Mutex<HashMap<String, FailedLoad>>>,
         cache: PathBuf,
+        disk: Arc<Mutex<DiskCache>>,
         ctx: egui::Context
     ) -> anyhow::Result<()>  {
         println!("image cache dir: {}", cache.to_str().unwrap());
         let client = reqwest::Client::builder().user_agent("some fuckass rust app that looks like the 2015 xbox one guide").build();
         if let Err(err) = client { return Err(anyhow::Error::msg(format!("{:?}", err))); }
         let client = client.unwrap();
-        'outer: loop {
-            while let Ok(req) = rx1.try_recv() {
-                let req = match req {
-                    LoaderRequest::Shutdown => break 'outer Ok(()),
-                    LoaderRequest::GetImg(resdb_path) => resdb_path,
-                };
-                
-                if req.is_empty() || !req.contains(".") { println!("empty or dot: {}", req); continue; }
-                let split_idx = if let Some(pos) = req.find("://") { pos } else { println!("no beginner: {}", req); continue; };
-                let (prefix, path) = req.split_at(split_idx+(if req.find(":///").is_some() {4} else {3}));
-                
-                // idk why but i'm gonna support HTTP urls too!
-                let web_path = if prefix.eq("resdb:///") {
-                    let (important, _webp) = path.split_at(path.find(".").unwrap());
-                    format!("https://assets.resonite.com/{}", important)
-                } else if prefix.starts_with("http") {
-                    format!("{}{}", prefix, path)
+
+        let shared = LoaderShared {
+            client, cache, disk, map, audio, wanted, failed, ctx,
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+            downloads: Arc::new(Semaphore::new(MAX_DOWNLOADS)),
+        };
+        // dropping this on the way out aborts whatever's still downloading
+        let mut tasks = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                req = rx1.recv() => {
+                    let (req, is_audio) = match req {
+                        None | Some(LoaderRequest::Shutdown) => break Ok(()),
+                        Some(LoaderRequest::GetImg(resdb_path)) => (resdb_path, false),
+                        Some(LoaderRequest::GetAudio(resdb_path)) => (resdb_path, true),
+                    };
+                    if !shared.in_flight.lock().unwrap().insert(req.clone()) { continue; }
+                    tasks.spawn(Self::load(shared.clone(), req, is_audio));
+                },
+                // just reaping finished ones so the set doesn't grow forever
+                Some(_) = tasks.join_next() => {},
+            }
+        }
+    }
+
+    /// One download and decode, from waiting for a slot to putting the result where the UI can see it
+    async fn load(shared: LoaderShared, req: String, is_audio: bool) {
+        let permit = shared.downloads.clone().acquire_owned().await;
+
+        // by the time we got a slot it might've scrolled away, forget it so it gets asked for again if it comes back
+        if !is_audio && !Self::still_wanted(&shared, &req) {
+            shared.map.lock().unwrap().remove(&req);
+            shared.in_flight.lock().unwrap().remove(&req);
+            return;
+        }
+
+        let file_path = match Self::fetch(&shared.client, &shared.cache, &shared.disk, &req).await {
+            Ok(path) => Some(path),
+            Err(err) => {
+                println!("{}", err);
+                if is_audio {
+                    shared.audio.lock().unwrap().insert(req.clone(), LoadableAudio::Failed(err));
                 } else {
-                    println!("doesn't match spec: {} {}", prefix, path);
-                    continue;
-                };
-                
-                let mut file_path = cache.clone();
-                file_path.push(path.replace("/", ""));
-
-                if !file_path.exists() {
-                    let dl = client.get(&web_path).send().await;
-
-                    if let Err(err) = dl {
-                        println!("Failed to download {}! Reason: {:?}", &web_path, &err);
-                        continue;
-                    } let dl = dl.unwrap();
-
-                    let body = dl.bytes().await?;
-                    let file = File::create(&file_path);
-                    if file.is_err() {
-                        println!("Failed to create {:?}! Reason: {:?}", &file_path, &file);
-                        continue;
-                    } let mut file = file.unwrap();
-
-                    if let Err(err) = file.write_all(&body) {
-                        println!("Failed to copy file! Reason: {:?}", err);
-                        continue;
-                    }
+                    Self::record_failure(&shared, &req, err);
                 }
+                None
+            },
+        };
+        drop(permit);
 
-                let file_read = Self::load_from_fs(ctx.clone(), &file_path);
+        // decoding's all cpu, keep it off the executor so it doesn't hold up other downloads
+        if let Some(file_path) = file_path {
+            let shared0 = shared.clone();
+            let req0 = req.clone();
+            let decoded = tokio::task::spawn_blocking(move || {
+                if is_audio {
+                    let clip = match AudioClip::decode(&file_path) {
+                        Ok(clip) => LoadableAudio::Loaded(Arc::new(clip)),
+                        Err(err) => { println!("Failed to decode audio {:?}! {:?}", &file_path, err); LoadableAudio::Failed(format!("{}", err)) },
+                    };
+                    shared0.audio.lock().unwrap().insert(req0, clip);
+                    return;
+                }
 
-                if let Ok(fil) = file_read {
-                    let mut map = map.lock().unwrap();
-                    map.insert(req.clone(), Some(fil));
-                } else if let Err(err) = file_read {
-                    println!("Failed to read image! {:?}", err);
+                match Self::load_from_fs(shared0.ctx.clone(), &file_path) {
+                    Ok(fil) => {
+                        shared0.failed.lock().unwrap().remove(&req0);
+                        shared0.map.lock().unwrap().insert(req0, Some(fil));
+                    },
+                    Err(err) => {
+                        println!("Failed to read image! {:?}", err);
+                        // whatever's on disk is no good, don't let the retry pick it up again
+                        if let Some(name) = file_path.file_name().and_then(|n| n.to_str()) {
+                            shared0.disk.lock().unwrap().remove(name);
+                        }
+                        Self::record_failure(&shared0, &req0, format!("{}", err));
+                    },
                 }
+            }).await;
+            if let Err(err) = decoded {
+                println!("decode task for {} fell over: {:?}", req, err);
+                if !is_audio { Self::record_failure(&shared, &req, format!("{}", err)); }
             }
         }
+
+        shared.wanted.lock().unwrap().remove(&req);
+        shared.in_flight.lock().unwrap().remove(&req);
+        shared.ctx.request_repaint();
+    }
+
+    /// Backs off exponentially and takes it out of `db`, so `get_image` shows it as failed instead of loading
+    fn record_failure(shared: &LoaderShared, req: &str, reason: String) {
+        {
+            let mut failed = shared.failed.lock().unwrap();
+            let attempts = failed.get(req).map_or(0, |f| f.attempts) + 1;
+            let wait = RETRY_BASE.saturating_mul(1 << (attempts - 1).min(16)).min(RETRY_MAX);
+            failed.insert(req.to_owned(), FailedLoad { reason, attempts, retry_at: Instant::now() + wait });
+        }
+        // get_image takes db then failed, so don't hold failed while taking db
+        shared.map.lock().unwrap().remove(req);
+    }
+
+    /// Whether anything's drawn this recently enough to be worth downloading
+    fn still_wanted(shared: &LoaderShared, req: &str) -> bool {
+        let last = shared.wanted.lock().unwrap().get(req).copied();
+        match last {
+            Some(frame) => shared.ctx.frame_nr() <= frame + CANCEL_AFTER_FRAMES,
+            None => true,
+        }
+    }
+
+    /// Resolves a `resdb:///` or http(s) uri and makes sure it's on disk, downloading it if it isn't
+    async fn fetch(client: &reqwest::Client, cache: &PathBuf, disk: &Mutex<DiskCache>, req: &str) -> Result<PathBuf, String> {
+        if req.is_empty() || !req.contains(".") { return Err(format!("empty or dot: {}", req)); }
+        let split_idx = if let Some(pos) = req.find("://") { pos } else { return Err(format!("no beginner: {}", req)); };
+        let (prefix, path) = req.split_at(split_idx+(if req.find(":///").is_some() {4} else {3}));
+        
+        // idk why but i'm gonna support HTTP urls too!
+        // resdb names are the sha256 of the asset, so those we can check. http ones we just have to trust
+        let (web_path, hash) = if prefix.eq("resdb:///") {
+            let (important, _webp) = path.split_at(path.find(".").unwrap());
+            let hash = Some(important.to_ascii_lowercase()).filter(|h| h.len() == 64 && h.chars().all(|c| c.is_ascii_hexdigit()));
+            (crate::ENDPOINTS.asset_url(important), hash)
+        } else if prefix.starts_with("http") {
+            (format!("{}{}", prefix, path), None)
+        } else {
+            return Err(format!("doesn't match spec: {} {}", prefix, path));
+        };
+        
+        let file_name = path.replace("/", "");
+        let mut file_path = cache.clone();
+        file_path.push(&file_name);
+
+        if file_path.exists() {
+            let intact = match &hash {
+                Some(hash) => {
+                    let on_disk = file_path.clone();
+                    let digest = tokio::task::spawn_blocking(move || sha256::try_digest(on_disk.as_path())).await;
+                    matches!(digest, Ok(Ok(digest)) if digest.eq_ignore_ascii_case(hash))
+                },
+                None => true,
+            };
+            if intact {
+                disk.lock().unwrap().touch(&file_name);
+                return Ok(file_path);
+            }
+            // half written by an old version, or the disk ate it, either way get a fresh one
+            println!("cached {} doesn't match its hash, downloading it again", file_name);
+            disk.lock().unwrap().remove(&file_name);
+        }
+
+        let dl = client.get(&web_path).send().await
+            .and_then(|dl| dl.error_for_status())
+            .map_err(|err| format!("Failed to download {}! Reason: {:?}", &web_path, err))?;
+        let body = dl.bytes().await
+            .map_err(|err| format!("Failed to download {}! Reason: {:?}", &web_path, err))?;
+
+        if let Some(hash) = &hash {
+            let digest = sha256::digest(body.as_ref());
+            if !digest.eq_ignore_ascii_case(hash) {
+                return Err(format!("Downloaded {} but it hashes to {}, not keeping it", &web_path, digest));
+            }
+        }
+
+        // write it next door and move it into place, so nothing ever sees half a file under the real name
+        let tmp_path = cache.join(format!("{}.tmp", file_name));
+        let write = File::create(&tmp_path)
+            .and_then(|mut file| file.write_all(&body).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&tmp_path, &file_path));
+        if let Err(err) = write {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(format!("Failed to save {:?}! Reason: {:?}", &file_path, err));
+        }
+        disk.lock().unwrap().insert(&file_name, body.len() as u64);
+        Ok(file_path)
     }
 
     pub fn load_from_fs(ctx: egui::Context, path: &PathBuf) -> anyhow::Result<TextureHandle> {
@@ -172,6 +371,31 @@ impl ResDbImageCache {
     /// tells the thread to \*lightning\*
     pub fn shutdown(&mut self) {
         self.tx.send(LoaderRequest::Shutdown).unwrap();
+        // here and not on the loader, the runtime might be gone before it gets to it
+        if let Some(disk) = &self.disk {
+            disk.lock().unwrap().save();
+        }
+    }
+
+    /// (bytes used, bytes allowed) on disk, if there's a cache folder at all
+    pub fn disk_usage(&self) -> Option<(u64, u64)> {
+        self.disk.as_ref().map(|disk| {
+            let disk = disk.lock().unwrap();
+            (disk.usage(), disk.max_size())
+        })
+    }
+
+    pub fn set_max_size(&mut self, max_size: u64) {
+        if let Some(disk) = &self.disk {
+            disk.lock().unwrap().set_max_size(max_size);
+        }
+    }
+
+    /// Deletes everything downloaded. Whatever's already on screen stays until restart
+    pub fn clear_disk(&mut self) {
+        if let Some(disk) = &self.disk {
+            disk.lock().unwrap().clear();
+        }
     }
 
     /// Accepts a `resdb://` string and gets an egui-drawable image (or lack thereof) from it
@@ -181,9 +405,19 @@ impl ResDbImageCache {
             if let Some(id) = img {
                 return LoadableImage::Loaded(id.id());
             } else {
+                self.wanted.lock().unwrap().insert(id.clone(), self.ctx.frame_nr());
                 return LoadableImage::Loading;
             }
         } else {
+            if let Some(failed) = self.failed.lock().unwrap().get(id) {
+                let now = Instant::now();
+                if now < failed.retry_at {
+                    // so it comes back on its own even if nothing else repaints
+                    self.ctx.request_repaint_after(failed.retry_at - now);
+                    return LoadableImage::Failed(failed.reason.clone(), ImageRetry { uri: id.clone(), failed: self.failed.clone() });
+                }
+            }
+            self.wanted.lock().unwrap().insert(id.clone(), self.ctx.frame_nr());
             // i don't want another enum so i just use existing as unloaded, and the option as loading/loaded
             // race conditions arise from setting None on the thread (loader thread is busy loading, and doesn't set None itself)
             // so we do it here
@@ -196,4 +430,17 @@ impl ResDbImageCache {
 
         LoadableImage::Unloaded
     }
+
+    /// Same as `get_image` but for audio assets, which get decoded instead of turned into a texture
+    pub fn get_audio(&mut self, id: &String) -> LoadableAudio {
+        let mut audio = self.audio.lock().unwrap();
+        if let Some(clip) = audio.get(id) {
+            return clip.clone();
+        }
+        audio.insert(id.clone(), LoadableAudio::Loading);
+        if let Err(err) = self.tx.send(LoaderRequest::GetAudio(id.to_string())) {
+            println!("Send error! {:?}", err);
+        }
+        LoadableAudio::Unloaded
+    }
 }
\ No newline at end of file
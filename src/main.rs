@@ -2,15 +2,16 @@
 #![allow(unused_imports)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use std::{collections::{BTreeMap, HashMap}, future::IntoFuture, sync::Arc};
+use std::{collections::{BTreeMap, HashMap, HashSet}, future::IntoFuture, sync::Arc};
 
 use keyring::{Entry, Result};
 
-use backend::thread::{BackendThread, BroadcastTarget, InitialLoginType, UiToReso, UserStatus, SessionUpdate};
+use backend::thread::{BackendThread, BroadcastTarget, InitialLoginType, ResoToUi, UiToReso, UserStatus, SessionUpdate};
 use eframe::{glow, Frame};
 use egui::{epaint::{text::cursor::PCursor, Shadow}, load::SizedTexture, mutex::Mutex, output::OutputEvent, pos2, vec2, Align2, Color32, FontData, FontDefinitions, FontId, ImageSource, Key, Layout, Margin, PointerButton, Pos2, Rect, RichText, Rounding, Stroke, TextEdit, TextureId, UiStackInfo, Vec2, Widget};
 use humansize::{SizeFormatter, DECIMAL};
 use image::{LoadableImage, ResDbImageCache};
+use time_format::TimeFormat;
 use log::{debug, error};
 use tokio;
 
@@ -24,8 +25,12 @@ mod self_helpers;
 mod bridge;
 
 pub mod image;
+pub mod audio;
+pub mod time_format;
+pub mod disk_cache;
+pub mod rich_text;
 
-use api::{client::{Contact, Message, UserInfo}, login};
+use api::{client::{Contact, Message, Record, UserInfo}, endpoints::Endpoints, login, message_store::MessageStore};
 use widgets::{button::metro_button, loadable_image::loadable_image, page_header::page_header, segoe_boot_spinner::{self, SegoeBootSpinner}, toggle_switch::{self, toggle_ui}, user_info::{uid_to_color, user_info_widget, UserInfoVariant}};
 
 const KEYRING_SERVICE: &str = "com.headassbtw";
@@ -68,7 +73,10 @@ pub struct LoginSettings {
 struct LoginDetails {
     username: String,
     password: String,
-    remember_me: bool
+    remember_me: bool,
+    /// set once the server asks for a 2FA code, flips the sign in page to its second step
+    needs_totp: bool,
+    totp: String,
 }
 
 struct TemporaryEntryFields {
@@ -93,6 +101,24 @@ pub struct TemplateApp {
     current_page: usize,
     entry_fields: TemporaryEntryFields,
     cached_user_infos: HashMap<String, UserInfo>,
+    /// conversations we're currently fetching older messages for
+    history_loading: HashSet<String>,
+    /// conversations the server's got nothing older for
+    history_exhausted: HashSet<String>,
+    /// conversations where getting older messages failed, left alone until you retry or scroll away
+    history_failed: HashSet<String>,
+    /// conversations that just got a page, no more until you've scrolled off the top and back
+    history_hold: HashSet<String>,
+    /// how tall each conversation was when we asked for more, to keep your place once the older stuff lands above it
+    history_anchor: HashMap<String, f32>,
+    /// how dates and times get shown everywhere, saved between runs
+    time_format: TimeFormat,
+    /// how big the image cache folder's allowed to get, saved between runs
+    image_cache_max_mb: u64,
+    /// ask before sending people off to a website from a message, saved between runs
+    confirm_links: bool,
+    /// web link waiting on the user to say yes
+    pending_link: Option<String>,
     image_cache: ResDbImageCache,
 }
 
@@ -105,16 +131,23 @@ pub struct FrontendNotification {
     icon: FrontendNotificationIcon,
     text: String,
     sub: String,
+    /// where clicking it takes you, if anywhere
+    action: Option<FrontendPage>,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 enum FrontendPage {
     SignInPage,
     ProfilePage(String),
     ConversationPage(String),
     FriendsPage,
+    FriendRequestsPage,
     UserSearchPage,
     SessionsPage,
+    /// details for one session, by session ID. The copy's from an invite, only used if the live list doesn't have it
+    SessionPage(String, Option<Box<SessionUpdate>>),
+    /// a shared item, opened from a resrec link
+    RecordPage(Box<Record>),
     MessagesPage,
     NotificationsPage,
     LoadingPage,
@@ -139,19 +172,23 @@ pub const HOVER_COL: Color32 = Color32::from_gray(51);
 lazy_static! { // sue me.
     pub static ref CONTACTS_LIST: Mutex<HashMap<String, Contact>> = Mutex::new(HashMap::new());
     pub static ref USER_STATUSES: Mutex<HashMap<String, UserStatus>> = Mutex::new(HashMap::new());
-    pub static ref MESSAGE_CACHE: Mutex<HashMap<String, Vec<Message>>> = Mutex::new(HashMap::new());
+    pub static ref MESSAGE_CACHE: Mutex<MessageStore> = Mutex::new(MessageStore::new());
+    pub static ref ENDPOINTS: Endpoints = Endpoints::load();
     pub static ref SESSION_CACHE: Mutex<HashMap<String, SessionUpdate>> = Mutex::new(HashMap::new());
-    /// THIS FUCKING SUCKS!
-    pub static ref REFRESH_UI: Mutex<bool> = Mutex::new(false);
+    /// hub callbacks poke this when they've changed something, so the backend forwards HUB_EVENTS and repaints
+    pub static ref HUB_WAKE: tokio::sync::Notify = tokio::sync::Notify::new();
 
     pub static ref THIS_FUCKING_SUCKS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    /// hub callbacks can't see the backend's channel, so they leave stuff here for it to forward
+    pub static ref HUB_EVENTS: Mutex<Vec<ResoToUi>> = Mutex::new(Vec::new());
 }
 
 fn icon_notification(icon: &str, header: &str, details: &str) -> FrontendNotification {
     FrontendNotification {
         icon: FrontendNotificationIcon::SegoeIcon(icon.to_owned()),
         text: header.to_owned(),
-        sub: details.to_owned()
+        sub: details.to_owned(),
+        action: None,
     }
 }
 
@@ -180,6 +217,16 @@ impl TemplateApp {
 
         fonts.families.insert(egui::FontFamily::Name("MDL2 Icons".into()), vec!["MDL2 Icons".to_owned()]);
         fonts.families.insert(egui::FontFamily::Name("Segoe Boot".into()), vec!["Segoe Boot".to_owned()]);
+
+        // no bold Segoe UI bundled, but windows comes with one. without it <b> gets regular with some spacing
+        let bold = std::fs::read("C:\\Windows\\Fonts\\segoeuib.ttf").ok();
+        rich_text::set_bold_face(bold.is_some());
+        let mut bold_family = vec!["Segoe UI".to_owned(), "MDL2 Icons".to_owned()];
+        if let Some(bold) = bold {
+            fonts.font_data.insert("Segoe UI Bold".to_owned(), egui::FontData::from_owned(bold));
+            bold_family.insert(0, "Segoe UI Bold".to_owned());
+        }
+        fonts.families.insert(egui::FontFamily::Name(rich_text::BOLD_FAMILY.into()), bold_family);
         
         fonts.families.get_mut(&egui::FontFamily::Proportional).unwrap().insert(0, "Segoe UI".to_owned());
         fonts.families.get_mut(&egui::FontFamily::Proportional).unwrap().insert(0, "MDL2 Icons".to_owned());
@@ -202,6 +249,9 @@ impl TemplateApp {
         let name: String = if let Some(storage) = cc.storage {
             eframe::get_value(storage, "username").unwrap_or("".to_string())
         } else { "".to_string() };
+        let time_format: TimeFormat = cc.storage.and_then(|storage| eframe::get_value(storage, "time_format")).unwrap_or_default();
+        let image_cache_max_mb: u64 = cc.storage.and_then(|storage| eframe::get_value(storage, "image_cache_max_mb")).unwrap_or(disk_cache::DEFAULT_LIMIT_MB);
+        let confirm_links: bool = cc.storage.and_then(|storage| eframe::get_value(storage, "confirm_links")).unwrap_or(true);
 
         let creds = if !name.is_empty() {
             let entry = Entry::new(KEYRING_SERVICE, KEYRING_USER);
@@ -235,6 +285,15 @@ impl TemplateApp {
             current_page: 0,
             notifications: r,
             cached_user_infos: HashMap::new(),
+            history_loading: HashSet::new(),
+            history_exhausted: HashSet::new(),
+            history_failed: HashSet::new(),
+            history_hold: HashSet::new(),
+            history_anchor: HashMap::new(),
+            time_format,
+            image_cache_max_mb,
+            confirm_links,
+            pending_link: None,
             backend: BackendThread::new(&cc.egui_ctx, creds),
             entry_fields: TemporaryEntryFields {
                 user_info_query: String::new(),
@@ -243,10 +302,12 @@ impl TemplateApp {
                     remember_me: { !name.is_empty() },
                     username: name,
                     password: String::new(),
+                    needs_totp: false,
+                    totp: String::new(),
                 },
                 message_buffer: String::new(),
             },
-            image_cache: ResDbImageCache::new(cc.egui_ctx.clone()),
+            image_cache: ResDbImageCache::new(cc.egui_ctx.clone(), image_cache_max_mb * 1_000_000),
         }
     }
 }
@@ -274,6 +335,28 @@ fn sidebar_button(text: &str, ui: &mut egui::Ui) -> bool {
     ui.add_sized(vec2(SIDEBAR_ITEM_SIZE,SIDEBAR_ITEM_SIZE), egui::Button::new(egui::RichText::new(text).size(32.0).color(Color32::WHITE)).frame(false)).clicked()
 }
 
+/// Sidebar button with a count next to the icon, just the icon when `count` is 0
+fn sidebar_badge_button(icon: &str, font: FontId, count: usize, ui: &mut egui::Ui) -> bool {
+    let (rect, response) = ui.allocate_exact_size(vec2(SIDEBAR_ITEM_SIZE, SIDEBAR_ITEM_SIZE), egui::Sense::click());
+
+    // 10px between icon and text
+    // icon is 32px, text is 18px
+
+    if count == 0 {
+        ui.painter_at(rect).text(rect.center(), Align2::CENTER_CENTER, icon, font, Color32::WHITE);
+    } else {
+        let count_galley = ui.painter().layout(count.to_string(), FontId::proportional(18.0), Color32::WHITE, SIDEBAR_ITEM_SIZE);
+        let width = 32.0 + 10.0 + count_galley.rect.width();
+        let icon_center = pos2((rect.center().x - width / 2.0) + 16.0, rect.center().y + 3.0);
+        let label_pos = pos2(icon_center.x + 26.0, (rect.center().y - count_galley.rect.height() / 2.0) - 3.0);
+
+        ui.painter_at(rect).text(icon_center, Align2::CENTER_CENTER, icon, font, Color32::WHITE);
+        ui.painter().galley(label_pos, count_galley, Color32::WHITE)
+    }
+
+    response.clicked()
+}
+
 fn sidebar_top_pos(idx: u8) -> f32 {
     SIDEBAR_ITEM_SPACING + if idx == 0 { 0.0 } else { (20.0 + SIDEBAR_ITEM_SIZE) + ((SIDEBAR_ITEM_SIZE+4.0) * (idx-1) as f32) }
 }
@@ -283,6 +366,9 @@ impl eframe::App for TemplateApp {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, "username", &self.entry_fields.login_details.username);
+        eframe::set_value(storage, "time_format", &self.time_format);
+        eframe::set_value(storage, "confirm_links", &self.confirm_links);
+        eframe::set_value(storage, "image_cache_max_mb", &self.image_cache_max_mb);
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
@@ -333,8 +419,11 @@ impl eframe::App for TemplateApp {
                     FrontendPage::ProfilePage(id) => {if let Some(you) = &self.user_id { if you.eq(id) { 0 } else { 255 }} else { 255 }},
                     FrontendPage::ConversationPage(_) => 255,
                     FrontendPage::FriendsPage => 1,
+                    FrontendPage::FriendRequestsPage => 1,
                     FrontendPage::UserSearchPage => 255,
                     FrontendPage::SessionsPage => 2,
+                    FrontendPage::SessionPage(..) => 2,
+                    FrontendPage::RecordPage(_) => 255,
                     FrontendPage::MessagesPage => 3,
                     FrontendPage::NotificationsPage => 4,
                     FrontendPage::SettingsPage => 5,
@@ -380,34 +469,14 @@ impl eframe::App for TemplateApp {
                 
                 sidebar.style_mut().spacing.item_spacing.y = 4.0;
 
-                if sidebar_button("", &mut sidebar) { self.set_page(FrontendPage::FriendsPage); }  // friends
+                let (incoming_requests, _) = self.friend_request_counts();
+                if sidebar_badge_button("", FontId::proportional(32.0), incoming_requests, &mut sidebar) { self.set_page(FrontendPage::FriendsPage); } // friends
 
                 if sidebar_button("", &mut sidebar) { self.set_page(FrontendPage::SessionsPage); } // parties (sessions)
 
-                if sidebar_button("", &mut sidebar) { self.set_page(FrontendPage::MessagesPage); } // messages
-
-                if {
-                    let (rect, response) = sidebar.allocate_exact_size(vec2(SIDEBAR_ITEM_SIZE, SIDEBAR_ITEM_SIZE), egui::Sense::click());
+                if sidebar_badge_button("", FontId::proportional(32.0), self.unread_message_count(), &mut sidebar) { self.set_page(FrontendPage::MessagesPage); } // messages
 
-                    // 10px between icon and text
-                    // icon is 32px, text is 18px
-
-                    if self.notifications.len() <= 0 {
-                        sidebar.painter_at(rect).text(rect.center(), Align2::CENTER_CENTER, "", FontId::monospace(32.0), Color32::WHITE);
-                    } else {
-                        let count_galley = ui.painter().layout(self.notifications.len().to_string(), FontId::proportional(18.0), Color32::WHITE, SIDEBAR_ITEM_SIZE);
-                        let width = 32.0 + 10.0 + count_galley.rect.width();
-                        let icon_center = pos2((rect.center().x - width / 2.0) + 16.0, rect.center().y + 3.0);
-                        let label_pos = pos2(icon_center.x + 26.0, (rect.center().y - count_galley.rect.height() / 2.0) - 3.0);
-
-                        sidebar.painter_at(rect).text(icon_center, Align2::CENTER_CENTER, "", FontId::monospace(32.0), Color32::WHITE);
-                        sidebar.painter().galley(label_pos, count_galley, Color32::WHITE)
-                    }
-
-                    response.clicked()
-                } {
-                    self.set_page(FrontendPage::NotificationsPage);
-                }
+                if sidebar_badge_button("", FontId::monospace(32.0), self.notifications.len(), &mut sidebar) { self.set_page(FrontendPage::NotificationsPage); } // notifications
 
                 if sidebar_button("", &mut sidebar) { self.set_page(FrontendPage::SettingsPage); } // settings
 
@@ -432,7 +501,10 @@ impl eframe::App for TemplateApp {
                     FrontendPage::SignInPage => self.signin_page(page),
                     FrontendPage::ProfilePage(id) => self.profile_page(page, id.to_string()),
                     FrontendPage::FriendsPage => self.friends_page(page),
+                    FrontendPage::FriendRequestsPage => self.friend_requests_page(page),
                     FrontendPage::SessionsPage => self.sessions_page(page),
+                    FrontendPage::SessionPage(id, invite) => self.session_page(page, id.to_string(), invite.as_deref().cloned()),
+                    FrontendPage::RecordPage(record) => self.record_page(page, (**record).clone()),
                     FrontendPage::NotificationsPage => self.notifications_page(page),
                     FrontendPage::LoadingPage => self.loading_page(page),
                     FrontendPage::UserSearchPage => self.user_search_page(page),
@@ -444,6 +516,33 @@ impl eframe::App for TemplateApp {
             });
             
         });
+
+        // asked for by open_link, sits on top of whatever page we're on
+        if let Some(url) = self.pending_link.clone() {
+            let mut open = None;
+            egui::Window::new("Open link?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, vec2(0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.set_max_width(480.0);
+                    ui.label(RichText::new("This link goes somewhere outside ResBox, make sure you trust it.").size(18.0).color(SUBHEADER_COL));
+                    ui.add(egui::Label::new(RichText::new(&url).size(20.0).color(Color32::WHITE)).wrap());
+                    ui.style_mut().spacing.interact_size.y = 48.0;
+                    ui.horizontal(|ui| {
+                        if metro_button(ui, "Open", Some(("", 20.0))).clicked() { open = Some(true); }
+                        if metro_button(ui, "Cancel", Some(("", 20.0))).clicked() { open = Some(false); }
+                    });
+                });
+            match open {
+                Some(true) => {
+                    ctx.open_url(egui::OpenUrl::new_tab(&url));
+                    self.pending_link = None;
+                },
+                Some(false) => self.pending_link = None,
+                None => {},
+            }
+        }
     }
 
     fn on_exit(&mut self, _gl: Option<&glow::Context>) {
@@ -1,10 +1,23 @@
-use chrono::{DateTime, Datelike, Utc};
-use egui::{text::{LayoutJob, TextWrapping}, vec2, Align, Align2, Color32, FontId, Layout, Margin, Mesh, Pos2, RichText, Rounding, Shape, Stroke, TextEdit};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use egui::{text::{LayoutJob, TextWrapping}, vec2, Align, Align2, Color32, FontId, Layout, Margin, Mesh, Pos2, Rect, RichText, Rounding, Shape, Stroke, TextEdit};
 
-use crate::{api::client::{Contact, MessageType, ResDateTime}, backend::thread::UiToReso, disgusting_bullshit, widgets::{button::metro_button, page_header::page_header, user_info::{draw_user_pic_at, user_color_and_subtext, user_info_widget, UserInfoVariant}}, TemplateApp, CONTACTS_LIST, MESSAGE_CACHE};
+use crate::{api::{client::{Contact, MessageType, ResDateTime}, message_store::DeliveryState}, audio::LoadableAudio, backend::thread::UiToReso, disgusting_bullshit, icon_notification, rich_text::{link_at, rich_text_job}, widgets::{button::metro_button, page_header::page_header, audio_card::{audio_card, AUDIO_CARD_SIZE}, segoe_boot_spinner::SegoeBootSpinner, record_card::{record_card, record_details, RECORD_CARD_SIZE}, session_card::{session_card, SESSION_CARD_SIZE}, user_info::{draw_user_pic_at, user_color_and_subtext, user_info_widget, UserInfoVariant}}, FrontendPage, TemplateApp, ACCENT, CONTACTS_LIST, MESSAGE_CACHE};
+
+/// How far from the top you have to scroll before getting back there loads another page
+const HISTORY_REARM_DISTANCE: f32 = 48.0;
 
 impl TemplateApp {
     pub fn conversation_page(&mut self, ui: &mut egui::Ui, id: String) {
+        let blocked = CONTACTS_LIST.lock().get(&id).map_or(false, |c| c.is_blocked());
+
+        // only counts as read if you could actually be looking at it
+        let visible = ui.ctx().input(|i| i.focused && !i.viewport().minimized.unwrap_or(false));
+        if visible {
+            let read = MESSAGE_CACHE.lock().mark_conversation_read(&id, Utc::now());
+            if !read.is_empty() {
+                self.backend.tx.send(UiToReso::SignalMarkMessagesRead(id.clone(), read)).unwrap();
+            }
+        }
         {
             let contacts = CONTACTS_LIST.lock();
             if let Some(contact) = contacts.get(&id) {
@@ -49,7 +62,7 @@ impl TemplateApp {
                 }
                 
                 painter.text(text_anchor - vec2(0.0, 4.0), Align2::LEFT_BOTTOM, name, FontId::proportional(24.0), Color32::WHITE);
-                painter.text(text_anchor + vec2(0.0, 4.0), Align2::LEFT_TOP, subtext, FontId::proportional(20.0), Color32::from_gray(140));
+                painter.text(text_anchor + vec2(0.0, 4.0), Align2::LEFT_TOP, contact.relationship_label().unwrap_or(&subtext), FontId::proportional(20.0), Color32::from_gray(140));
             } else {
                 page_header(ui, "Message Page", "Oh fuck (user is not in contacts)");
             }
@@ -61,11 +74,25 @@ impl TemplateApp {
             
             
             ui.with_layout(Layout::top_down(egui::Align::Min), |messages| {
-                egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).show(messages, |ui| {
+                let scroll = egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).stick_to_bottom(true).show(messages, |ui| {
                     ui.style_mut().spacing.item_spacing.y = 24.0;
                     let max_text_width = ui.available_width() - 182.0; // 72 left, 40 right, 74 because microsoft felt like it
                     ui.allocate_space(vec2(ui.available_width(), 0.0));
 
+                    if self.history_loading.contains(&id) {
+                        ui.with_layout(Layout::top_down(egui::Align::Center), |spinner| {
+                            spinner.add(SegoeBootSpinner::new().size(32.0));
+                        });
+                    } else if self.history_failed.contains(&id) {
+                        let retry = ui.with_layout(Layout::top_down(egui::Align::Center), |failed| {
+                            failed.add(egui::Label::new(RichText::new("Couldn't load older messages, tap to retry").size(20.0).color(ACCENT)).sense(egui::Sense::click()))
+                        }).inner;
+                        if retry.clicked() {
+                            self.history_failed.remove(&id);
+                            self.history_hold.remove(&id);
+                        }
+                    }
+
                     // 72 left
                     // 40 right
                     // 330 text max width (width - 74?)
@@ -73,24 +100,23 @@ impl TemplateApp {
                     // messages should abide by this themselves, other text is fully centered
                     {
                         let messages = MESSAGE_CACHE.lock();
+                        let mut retry = None;
+                        let mut open_session = None;
+                        let mut exported = None;
+                        let mut open_link = None;
                         if let Some(msgs) = messages.get(&id) {
-                            let mut date: Option<&ResDateTime> = None;
-                            for message in msgs {
-                                let cur_date = &message.send_time;
-                                let should_draw_date: bool = if let Some(d) = date {
-                                    (d.0.timestamp() + 86400) < cur_date.0.timestamp() // more than a day later
-                                } else {
-                                    true
-                                };
-                                date = Some(&cur_date);
-                                
+                            // "Seen" only goes on the newest thing we sent, not every single one they've read
+                            let last_ours = msgs.iter().rev().find(|m| self.is_you(&m.sender_id)).map(|m| m.id.clone());
+                            let mut date: Option<NaiveDate> = None;
+                            for message in msgs.iter() {
+                                let local_send = message.send_time.0.with_timezone(&Local);
+                                // new local calendar day, not just 24 hours later
+                                let should_draw_date = date != Some(local_send.date_naive());
+                                date = Some(local_send.date_naive());
+
                                 if should_draw_date {
                                     ui.with_layout(Layout::top_down(egui::Align::Center), |new_date| {
-                                        let date = &date.unwrap();
-                                        // europeans seething and malding
-                                        // on a serious note i should probably check if this is affected by regional format info,
-                                        // but that would require setting my xbox to the wrong version of english and i would rather die
-                                        new_date.label(format!("{}/{}/{}", date.0.month(), date.0.day(), date.0.year()));
+                                        new_date.label(self.time_format.day_label(&message.send_time.0));
                                     });
                                 }
                                 let text = match message.message_type {
@@ -100,12 +126,25 @@ impl TemplateApp {
                                     MessageType::SessionInvite => "[Session Invite]",
                                 };
 
-                                let galley = ui.painter().layout(text.to_string(), FontId::proportional(24.0), Color32::WHITE, max_text_width);
-
+                                let (job, links) = rich_text_job(text, 24.0, Color32::WHITE, max_text_width);
+                                let galley = ui.painter().layout_job(job);
 
+                                // invites, items and voice messages get a card instead of text, if they parse
+                                let invite = message.session_invite();
+                                let record = message.record();
+                                let is_voice = message.message_type == MessageType::Sound;
+                                let content_size = if invite.is_some() {
+                                    vec2(SESSION_CARD_SIZE.x.min(max_text_width), SESSION_CARD_SIZE.y)
+                                } else if record.is_some() && is_voice {
+                                    vec2(AUDIO_CARD_SIZE.x.min(max_text_width), AUDIO_CARD_SIZE.y)
+                                } else if record.is_some() {
+                                    vec2(RECORD_CARD_SIZE.x.min(max_text_width), RECORD_CARD_SIZE.y)
+                                } else {
+                                    galley.rect.max.to_vec2()
+                                };
 
                                 // 16px all sides padding
-                                let (mut paint_rect, msg_resp) = ui.allocate_exact_size(vec2(galley.rect.max.x + 32.0, galley.rect.max.y + 56.0), egui::Sense::click());
+                                let (mut paint_rect, msg_resp) = ui.allocate_exact_size(content_size + vec2(32.0, 56.0), egui::Sense::click());
                                 if !ui.is_rect_visible(paint_rect) { continue; } // WE OPTIMIZED UP IN THIS BITCH 🔥
                                 paint_rect.max.y -= 14.0;                        // every time i do that, i have to google "fire emoji"
                                 let paint_rect = if self.is_you(&message.sender_id) { // Sometimes i envy mac users that can just type it, but then remember they're mac users and feel pity
@@ -129,7 +168,83 @@ impl TemplateApp {
                                     ui.painter().line_segment([paint_rect.min + vec2(0.0, paint_rect.height() + 14.0), paint_rect.max - vec2(paint_rect.width() - 14.0, 0.0)], Stroke::new(2.0, Color32::GRAY)); // diagonal
                                 }
 
-                                ui.painter().galley(paint_rect.min + vec2(16.0, 16.0), galley, Color32::GREEN);
+                                let card_rect = Rect::from_min_size(paint_rect.min + vec2(16.0, 16.0), content_size);
+                                if let Some(session) = invite {
+                                    if session_card(ui, card_rect, &mut self.image_cache, &session, ui.id().with(("invite", &message.id))).clicked() {
+                                        open_session = Some(session);
+                                    }
+                                } else if let (Some(record), true) = (&record, is_voice) {
+                                    let clip = match &record.asset_uri {
+                                        Some(uri) => self.image_cache.get_audio(uri),
+                                        None => LoadableAudio::Failed("no asset".to_owned()),
+                                    };
+                                    let card_resp = audio_card(ui, card_rect, &clip, ui.id().with(("voice", &message.id)));
+                                    if let LoadableAudio::Loaded(clip) = clip {
+                                        let popup_id = ui.id().with(("voice_export", &message.id));
+                                        if card_resp.clicked() {
+                                            ui.memory_mut(|mem| mem.toggle_popup(popup_id));
+                                        }
+                                        // no playback yet, saving it is the best we can do
+                                        egui::popup::popup_below_widget(ui, popup_id, &card_resp, egui::PopupCloseBehavior::CloseOnClickOutside, |ui| {
+                                            ui.set_min_width(240.0);
+                                            ui.style_mut().spacing.interact_size.y = 48.0;
+                                            let name = format!("voice-{}", message.id);
+                                            if metro_button(ui, &format!("Save as .{}", clip.original_ext()), Some(("", 20.0))).clicked() {
+                                                exported = Some(clip.export_original(&name));
+                                                ui.memory_mut(|mem| mem.close_popup());
+                                            }
+                                            if metro_button(ui, "Save as .wav", Some(("", 20.0))).clicked() {
+                                                exported = Some(clip.export_wav(&name));
+                                                ui.memory_mut(|mem| mem.close_popup());
+                                            }
+                                        });
+                                    }
+                                } else if let Some(record) = record {
+                                    let card_resp = record_card(ui, card_rect, &mut self.image_cache, &record, ui.id().with(("record", &message.id)));
+                                    let popup_id = ui.id().with(("record_details", &message.id));
+                                    if card_resp.clicked() {
+                                        ui.memory_mut(|mem| mem.toggle_popup(popup_id));
+                                    }
+                                    egui::popup::popup_below_widget(ui, popup_id, &card_resp, egui::PopupCloseBehavior::CloseOnClickOutside, |ui| {
+                                        record_details(ui, &record);
+                                    });
+                                } else {
+                                    let text_pos = paint_rect.min + vec2(16.0, 16.0);
+                                    if !links.is_empty() {
+                                        let link_resp = ui.interact(Rect::from_min_size(text_pos, galley.size()), ui.id().with(("links", &message.id)), egui::Sense::click());
+                                        if let Some(link) = link_resp.hover_pos().and_then(|pointer| link_at(&galley, &links, pointer - text_pos)) {
+                                            ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                                            if link_resp.clicked() {
+                                                open_link = Some(link.target.clone());
+                                            }
+                                        }
+                                    }
+                                    ui.painter().galley(text_pos, galley, Color32::GREEN);
+                                }
+
+                                // time (or how sending's going) goes next to the bubble, on the side facing the middle
+                                let time = self.time_format.time(&message.send_time.0);
+                                let (status, col) = match msgs.delivery(&message.id) {
+                                    DeliveryState::Pending => ("Sending...".to_owned(), Color32::GRAY),
+                                    DeliveryState::Failed => {
+                                        let retry_resp = ui.interact(paint_rect, ui.id().with(("retry", &message.id)), egui::Sense::click());
+                                        if retry_resp.clicked() {
+                                            retry = Some(message.id.clone());
+                                        }
+                                        ("Not sent, tap to retry".to_owned(), ACCENT)
+                                    },
+                                    DeliveryState::Sent => match &message.read_time {
+                                        Some(read) if last_ours.as_ref() == Some(&message.id) => {
+                                            (format!("{}  Seen {}", time, self.time_format.time(&read.0)), Color32::GRAY)
+                                        },
+                                        _ => (time, Color32::from_gray(140)),
+                                    },
+                                };
+                                if self.is_you(&message.sender_id) {
+                                    ui.painter().text(paint_rect.left_bottom() + vec2(-8.0, 0.0), Align2::RIGHT_BOTTOM, status, FontId::proportional(18.0), col);
+                                } else {
+                                    ui.painter().text(paint_rect.right_bottom() + vec2(8.0, 0.0), Align2::LEFT_BOTTOM, status, FontId::proportional(18.0), col);
+                                }
                             }
                         } else {
                             let header = "Go ahead, say hi";
@@ -148,8 +263,53 @@ impl TemplateApp {
 
                             ui.painter().text(ui.available_rect_before_wrap().center() - vec2(0.0, 68.0), Align2::CENTER_CENTER, "", FontId::monospace(96.0), Color32::WHITE);
                         }
+                        drop(messages);
+                        if let Some(message_id) = retry {
+                            self.backend.tx.send(UiToReso::SignalRetryMessage(id.clone(), message_id)).unwrap();
+                        }
+                        match exported {
+                            Some(Ok(path)) => self.notifications.push(icon_notification("", "Voice message saved", &path.to_string_lossy())),
+                            Some(Err(err)) => self.notifications.push(icon_notification("", "Couldn't save voice message", &format!("{}", err))),
+                            None => {},
+                        }
+                        if let Some(target) = open_link {
+                            self.open_link(ui.ctx(), &target);
+                        }
+                        if let Some(session) = open_session {
+                            // the hub might not have told us about it, the invite's copy is better than nothing.
+                            // it goes along with the page and not in the cache, otherwise dead invites would show up as live sessions
+                            let session_id = session.session_id.clone();
+                            self.set_page(FrontendPage::SessionPage(session_id, Some(Box::new(session))));
+                        }
                     }
                 });
+
+                // a page landed above you, push the scroll down by however much it added so you stay where you were
+                if !self.history_loading.contains(&id) {
+                    if let Some(before) = self.history_anchor.remove(&id) {
+                        let added = scroll.content_size.y - before;
+                        if added > 0.0 {
+                            let mut state = scroll.state;
+                            state.offset.y += added;
+                            state.store(messages.ctx(), scroll.id);
+                            messages.ctx().request_repaint();
+                        }
+                    }
+                }
+
+                // scrolled away from the top, the next time you get there counts as asking again
+                if scroll.state.offset.y > HISTORY_REARM_DISTANCE {
+                    self.history_hold.remove(&id);
+                    self.history_failed.remove(&id);
+                }
+
+                // scrolled to the top (or everything fits), go get older stuff
+                let waiting = self.history_loading.contains(&id) || self.history_hold.contains(&id) || self.history_failed.contains(&id);
+                if scroll.state.offset.y <= 1.0 && !waiting && !self.history_exhausted.contains(&id) {
+                    self.history_loading.insert(id.clone());
+                    self.history_anchor.insert(id.clone(), scroll.content_size.y);
+                    self.backend.tx.send(UiToReso::LoadMoreMessages(id.clone())).unwrap();
+                }
             });
 
             ui.allocate_ui_at_rect(bottom_rect, |bar| {
@@ -165,6 +325,12 @@ impl TemplateApp {
                         };
                     */
 
+                    if blocked {
+                        let (rect, _) = bar.allocate_exact_size(vec2(bottom_rect.width(), 68.0), egui::Sense::hover());
+                        bar.painter().text(rect.left_center() + vec2(bar.style().spacing.window_margin.left, -5.0), Align2::LEFT_CENTER, "You've blocked this person", FontId::proportional(24.0), Color32::GRAY);
+                        return;
+                    }
+
                     disgusting_bullshit(bar, false);
                     let marge = Margin { left: bar.style().spacing.window_margin.left, right: bar.style().spacing.window_margin.right, top: 12.0, bottom: 22.0 };
                     let res = bar.add_sized(vec2(bottom_rect.width(), 68.0), TextEdit::singleline(&mut self.entry_fields.message_buffer)
@@ -23,6 +23,12 @@ impl TemplateApp {
             self.set_page(FrontendPage::UserSearchPage);
         }
 
+        let (incoming, outgoing) = self.friend_request_counts();
+        let requests_label = if incoming + outgoing > 0 { format!("Friend requests ({})", incoming + outgoing) } else { "Friend requests".to_owned() };
+        if metro_button(ui, &requests_label, None).clicked() {
+            self.set_page(FrontendPage::FriendRequestsPage);
+        }
+
         
 
         egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).show(ui, |ui| {
@@ -56,7 +62,8 @@ impl TemplateApp {
             }
         
             for (id, user) in ctx_list {
-                if !user.is_accepted { continue; }
+                // requests either way live on their own page, blocked people don't live anywhere
+                if !user.is_friend() { continue; }
                 if user_info_widget(ui, &mut self.image_cache, UserInfoVariant::Contact(&user)).clicked() {
                     self.set_page(FrontendPage::ProfilePage(id.clone()));
                 }
@@ -1,9 +1,8 @@
 use std::cmp::Ordering;
 
-use chrono::{DateTime, Datelike};
 use egui::{pos2, text::{LayoutJob, LayoutSection, TextWrapping}, vec2, Align2, Color32, FontId, Pos2, RichText, Rounding, Stroke, TextFormat};
 
-use crate::{api::client::{Message, MessageType, ResDateTime}, widgets::{button::metro_button, page_header::page_header, user_info::{draw_user_pic_at, user_color_and_subtext, UserInfoVariant}}, FrontendPage, TemplateApp, CONTACTS_LIST, HOVER_COL, MESSAGE_CACHE};
+use crate::{api::{client::{Message, ResDateTime}, message_store::Conversation}, widgets::{button::metro_button, page_header::page_header, user_info::{draw_user_pic_at, user_color_and_subtext, UserInfoVariant}}, FrontendPage, TemplateApp, ACCENT, CONTACTS_LIST, HOVER_COL, MESSAGE_CACHE};
 
 impl TemplateApp {
     pub fn messages_page(&mut self, ui: &mut egui::Ui) {
@@ -19,7 +18,7 @@ impl TemplateApp {
         egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).show(ui, |ui| {
             let guh = MESSAGE_CACHE.lock();
             let contacts = CONTACTS_LIST.lock();
-            let mut hash_vec: Vec<(&String, &Vec<Message>)> = guh.iter().collect();
+            let mut hash_vec: Vec<(&String, &Conversation)> = guh.iter().collect();
 
             ui.style_mut().spacing.item_spacing.y = 4.0;
 
@@ -29,7 +28,7 @@ impl TemplateApp {
 
                 if let Some(first) = d.last() {
                     if let Some(first_cmp) = f.last() { 
-                        first_cmp.last_update_time.0.cmp(&first.last_update_time.0)
+                        first_cmp.send_time.0.cmp(&first.send_time.0)
                     } else {
                         Ordering::Less
                     }
@@ -39,6 +38,7 @@ impl TemplateApp {
             });
 
             for (id, vec) in hash_vec {
+                if contacts.get(id).map_or(false, |c| c.is_blocked()) { continue; }
                 if let Some(last) = vec.last() {
                     let mut rect = ui.cursor().clone();
                     rect.max.y = rect.min.y + 104.0;
@@ -72,12 +72,7 @@ impl TemplateApp {
                         }
                     };
 
-                    let msg = match last.message_type {
-                        MessageType::Text => &last.content,
-                        MessageType::Object => "Shared content",
-                        MessageType::Sound => "Shared content",
-                        MessageType::SessionInvite => "[Session Invite]",
-                    };
+                    let msg = last.preview();
 
                     draw_user_pic_at(ui, img_rect, &mut self.image_cache, pfp_draw_variant);
 
@@ -97,15 +92,24 @@ impl TemplateApp {
 
                     let date_pos = pos2(bound_rect.max.x, u_rect.max.y - 4.0);
 
-                    let date = &last.send_time.0;
-                    ui.painter().text(date_pos, Align2::RIGHT_BOTTOM, format!("{}/{}/{}", date.month(), date.day(), date.year()), FontId::proportional(18.0), Color32::from_gray(140));
+                    ui.painter().text(date_pos, Align2::RIGHT_BOTTOM, self.time_format.relative(&last.send_time.0), FontId::proportional(18.0), Color32::from_gray(140));
+
+                    // unread ones get a count on the right and a brighter preview
+                    let unread = vec.unread_count();
+                    let badge_width = if unread > 0 {
+                        let b_galley = ui.painter().layout_no_wrap(unread.to_string(), FontId::proportional(20.0), ACCENT);
+                        let width = b_galley.size().x + 12.0;
+                        ui.painter().galley(pos2(bound_rect.max.x - b_galley.size().x, left_center.y + 4.0), b_galley, ACCENT);
+                        width
+                    } else { 0.0 };
+                    let msg_col = if unread > 0 { Color32::WHITE } else { Color32::GRAY };
 
-                    let mut message_job = LayoutJob::simple_singleline(msg.to_owned(), FontId::proportional(20.0), Color32::GRAY);
-                    message_job.wrap = TextWrapping::truncate_at_width(bound_rect.width());
+                    let mut message_job = LayoutJob::simple_singleline(msg, FontId::proportional(20.0), msg_col);
+                    message_job.wrap = TextWrapping::truncate_at_width(bound_rect.width() - badge_width);
 
                     let m_galley = ui.painter().layout_job(message_job);
                     let m_rect = Align2::LEFT_TOP.anchor_size(left_center, m_galley.size());
-                    ui.painter().galley(m_rect.min + vec2(0.0, 4.0), m_galley, Color32::GRAY);
+                    ui.painter().galley(m_rect.min + vec2(0.0, 4.0), m_galley, msg_col);
 
                     //ui.painter().text(left_center, Align2::LEFT_BOTTOM, "USERNAME", FontId::proportional(24.0), Color32::WHITE);
                     //ui.painter().text(left_center, Align2::LEFT_TOP, "MESSAGE", FontId::proportional(20.0), Color32::GRAY);
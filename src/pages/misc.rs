@@ -1,6 +1,8 @@
-use egui::{vec2, Margin, TextEdit};
+use chrono::Utc;
+use egui::{vec2, Margin, RichText, TextEdit};
+use humansize::{SizeFormatter, DECIMAL};
 
-use crate::{backend::{self, thread::UiToReso}, disgusting_bullshit, icon_notification, widgets::{button::metro_button, page_header::page_header, segoe_boot_spinner::SegoeBootSpinner, toggle_switch::toggle_ui, user_info::{user_info_widget, UserInfoVariant}}, FrontendPage, TemplateApp, CONTENT_LEFT_PAD, CONTENT_RIGHT_PAD, KEYRING_SERVICE, KEYRING_USER, TEXT_COL};
+use crate::{backend::{self, thread::UiToReso}, disgusting_bullshit, disk_cache::SIZE_LIMITS_MB, icon_notification, widgets::{button::metro_button, page_header::page_header, segoe_boot_spinner::SegoeBootSpinner, toggle_switch::toggle_ui, user_info::{user_info_widget, UserInfoVariant}}, FrontendPage, TemplateApp, CONTENT_LEFT_PAD, CONTENT_RIGHT_PAD, KEYRING_SERVICE, KEYRING_USER, SUBHEADER_COL, TEXT_COL};
 
 impl TemplateApp {
     pub fn signin_page(&mut self, ui: &mut egui::Ui) {
@@ -13,7 +15,9 @@ impl TemplateApp {
         test_rect.max.y = test_rect.min.y + 60.0;
         let marge = Margin { left: CONTENT_LEFT_PAD, right: CONTENT_RIGHT_PAD, top: 12.0, bottom: 12.0 };
 
-        if !self.logged_in && self.can_attempt_login{
+        if !self.logged_in && self.can_attempt_login && self.entry_fields.login_details.needs_totp {
+            self.signin_totp_step(ui, test_rect, marge);
+        } else if !self.logged_in && self.can_attempt_login{
             disgusting_bullshit(ui, false);
             ui.add_sized(test_rect.size(), TextEdit::singleline(&mut self.entry_fields.login_details.username)
                 .desired_width(test_rect.width())
@@ -41,12 +45,49 @@ impl TemplateApp {
             toggle_ui(ui, "Remember Me", &mut self.entry_fields.login_details.remember_me);
             
             if metro_button(ui, "Log in", Some(("", 24.0))).clicked() {
-                self.backend.tx.send(backend::thread::UiToReso::TokenRequestCredentials(self.entry_fields.login_details.username.clone(), self.entry_fields.login_details.password.clone(), self.entry_fields.login_details.remember_me)).unwrap();
-                self.can_attempt_login = false;      
-                self.set_page(FrontendPage::LoadingPage);
+                self.send_login_request(None);
             }
         }
     }
+
+    /// Second half of signing in to a 2FA account, the username and password from the first half are still around
+    fn signin_totp_step(&mut self, ui: &mut egui::Ui, field_rect: egui::Rect, marge: Margin) {
+        ui.label(egui::RichText::new(format!("Enter the 6-digit code from your authenticator app for {}", self.entry_fields.login_details.username)).size(20.0).color(SUBHEADER_COL));
+        ui.allocate_space(vec2(0.0, 12.0));
+
+        disgusting_bullshit(ui, false);
+        let code_re = ui.add_sized(field_rect.size(), TextEdit::singleline(&mut self.entry_fields.login_details.totp)
+            .desired_width(field_rect.width())
+            .vertical_align(egui::Align::Center)
+            .text_color(TEXT_COL)
+            .hint_text("Code")
+            .margin(marge)
+            .char_limit(6)
+            .font(egui::FontId::new(24.0, eframe::epaint::FontFamily::Proportional))
+            .frame(false)
+        );
+        self.entry_fields.login_details.totp.retain(|c| c.is_ascii_digit());
+
+        let code_ready = self.entry_fields.login_details.totp.len() == 6;
+        let entered = code_re.lost_focus() && code_re.ctx.input(|i| i.key_pressed(egui::Key::Enter));
+
+        let verify = ui.add_enabled_ui(code_ready, |ui| metro_button(ui, "Verify", Some(("", 24.0)))).inner;
+        if code_ready && (verify.clicked() || entered) {
+            let code = self.entry_fields.login_details.totp.clone();
+            self.send_login_request(Some(code));
+        }
+
+        if metro_button(ui, "Back", Some(("", 24.0))).clicked() {
+            self.entry_fields.login_details.needs_totp = false;
+            self.entry_fields.login_details.totp.clear();
+        }
+    }
+
+    fn send_login_request(&mut self, totp: Option<String>) {
+        self.backend.tx.send(backend::thread::UiToReso::TokenRequestCredentials(self.entry_fields.login_details.username.clone(), self.entry_fields.login_details.password.clone(), self.entry_fields.login_details.remember_me, totp)).unwrap();
+        self.can_attempt_login = false;      
+        self.set_page(FrontendPage::LoadingPage);
+    }
     
     pub fn user_search_page(&mut self, ui: &mut egui::Ui) {
         page_header(ui, "Query Users", &self.username());
@@ -110,6 +151,32 @@ impl TemplateApp {
                 }
             }
         }
+        if self.logged_in && metro_button(ui, "Sign out", Some(("", 24.0))).clicked() {
+            self.backend.tx.send(UiToReso::LogoutRequest).unwrap();
+            self.can_attempt_login = false;
+            self.set_page(FrontendPage::LoadingPage);
+        }
+        toggle_ui(ui, "24-hour clock", &mut self.time_format.twenty_four_hour);
+        if metro_button(ui, &format!("Date order: {}", self.time_format.date_order.label()), None).clicked() {
+            self.time_format.date_order = self.time_format.date_order.next();
+        }
+        ui.label(RichText::new(format!("Now: {}", self.time_format.date_time(&Utc::now()))).color(SUBHEADER_COL));
+        toggle_ui(ui, "Ask before opening links", &mut self.confirm_links);
+
+        if let Some((used, max)) = self.image_cache.disk_usage() {
+            ui.label(RichText::new(format!("Image cache: {} of {}", SizeFormatter::new(used, DECIMAL), SizeFormatter::new(max, DECIMAL))).color(SUBHEADER_COL));
+            if metro_button(ui, &format!("Image cache limit: {} MB", self.image_cache_max_mb), None).clicked() {
+                // cycles through the presets, anything odd from an old config goes back to the start
+                let idx = SIZE_LIMITS_MB.iter().position(|mb| *mb == self.image_cache_max_mb).map_or(0, |i| (i + 1) % SIZE_LIMITS_MB.len());
+                self.image_cache_max_mb = SIZE_LIMITS_MB[idx];
+                self.image_cache.set_max_size(self.image_cache_max_mb * 1_000_000);
+            }
+            if metro_button(ui, "Clear image cache", Some(("", 24.0))).clicked() {
+                self.image_cache.clear_disk();
+                self.notifications.push(icon_notification("", "Image cache cleared", &format!("Freed {}", SizeFormatter::new(used, DECIMAL))));
+            }
+        }
+
         if metro_button(ui, "Request Status", None).clicked() {
             self.backend.tx.send(UiToReso::SignalRequestStatus(None, false)).unwrap();
         }
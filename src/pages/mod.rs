@@ -1,7 +1,9 @@
 mod profile;
 mod friends;
+mod requests;
 mod messages;
 mod conversation;
 mod notifications;
 mod sessions;
-mod misc;
\ No newline at end of file
+mod misc;
+mod records;
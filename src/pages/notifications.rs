@@ -18,12 +18,20 @@ impl TemplateApp {
         egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).show(ui, |ui| {
         
 
+            let mut open_page = None;
             for i in (0)..(self.notifications.len() as usize) {
                 let assets = self.notifications.get((self.notifications.len() as usize-1) - i).unwrap();
                 let mut rect = ui.available_rect_before_wrap();
                 rect.max.y = rect.min.y + ui.style().spacing.interact_size.y;
-                
-                ui.painter().rect_filled(rect, Rounding::same(0.0), ui.style().visuals.widgets.hovered.bg_fill);
+
+                let bg = if let Some(page) = &assets.action {
+                    let resp = ui.interact(rect, ui.id().with(("notification", i)), egui::Sense::click());
+                    if resp.clicked() {
+                        open_page = Some(page.clone());
+                    }
+                    if resp.is_pointer_button_down_on() { ui.style().visuals.widgets.active.bg_fill } else { ui.style().visuals.widgets.hovered.bg_fill }
+                } else { ui.style().visuals.widgets.hovered.bg_fill };
+                ui.painter().rect_filled(rect, Rounding::same(0.0), bg);
 
                 ui.horizontal(|notif: &mut egui::Ui| {
                     notif.allocate_space(vec2(72.0 - notif.cursor().left(),0.0));
@@ -58,7 +66,10 @@ impl TemplateApp {
                     });
 
                 });
-            }    
+            }
+            if let Some(page) = open_page {
+                self.set_page(page);
+            }
         });
     }
 
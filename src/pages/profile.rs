@@ -1,9 +1,9 @@
 use egui::{vec2, Align2, Color32, FontId};
 
 use crate::{
-    widgets::{
-        button::metro_button, loadable_image::loadable_image, user_info::{uid_to_color, user_color_and_subtext}
-    }, FrontendPage, TemplateApp, CONTACTS_LIST, HOVER_COL, SESSION_CACHE, THIS_FUCKING_SUCKS, USER_STATUSES
+    api::client::{ContactAction, ContactStatus}, backend::thread::UiToReso, widgets::{
+        button::metro_button, loadable_image::{image_retry, loadable_image}, user_info::{uid_to_color, user_color_and_subtext}
+    }, FrontendPage, TemplateApp, CONTACTS_LIST, HOVER_COL, SUBHEADER_COL, SESSION_CACHE, THIS_FUCKING_SUCKS, USER_STATUSES
 };
 
 
@@ -41,6 +41,7 @@ impl TemplateApp {
         if let Some(path) = pfp_path{
             let loadable = self.image_cache.get_image(&path);
             loadable_image(ui, &loadable, pfp_rect, "", HOVER_COL, 142.0, false);
+            image_retry(ui, &loadable, pfp_rect);
         } else {
             ui.painter().circle_filled(pfp_rect.center(), 142.0, uid_to_color(&id));
             ui.painter().text(pfp_rect.center(), Align2::CENTER_CENTER, "", FontId::proportional(142.0), Color32::WHITE);
@@ -78,15 +79,18 @@ impl TemplateApp {
         let (col, subtext) = user_color_and_subtext(&id);
         //TODO: add subtext
 
-        let sub_basis = ui.painter().text(name_pos, Align2::CENTER_TOP, name, FontId::proportional(24.0), Color32::WHITE);
+        let sub_basis = ui.painter().text(name_pos, Align2::CENTER_TOP, &name, FontId::proportional(24.0), Color32::WHITE);
         let id_pos = sub_basis.min + vec2(sub_basis.width() / 2.0, 38.0);
-        ui.painter().text(id_pos, Align2::CENTER_TOP, &id, FontId::proportional(24.0), Color32::GRAY);
+        let id_rect = ui.painter().text(id_pos, Align2::CENTER_TOP, &id, FontId::proportional(24.0), Color32::GRAY);
+        if let Some(label) = CONTACTS_LIST.lock().get(&id).and_then(|c| c.relationship_label()) {
+            ui.painter().text(id_rect.center_bottom() + vec2(0.0, 8.0), Align2::CENTER_TOP, label, FontId::proportional(20.0), SUBHEADER_COL);
+        }
         let stat_pos = sub_basis.min + vec2(-18.0, 16.0);
         if let Some(col) = col {
             ui.painter().circle_filled(stat_pos, 6.0, col);
         }
 
-        avail_rect.min.y += 80.0;
+        avail_rect.min.y += 112.0;
         ui.allocate_space(vec2(0.0, avail_rect.min.y - ui.cursor().min.y));
 
         ui.style_mut().spacing.interact_size.y = 60.0;
@@ -102,11 +106,40 @@ impl TemplateApp {
             self.set_page(FrontendPage::ConversationPage(id.clone()));
         }
         
-        ui.set_enabled(false);
-        if !is_contact {
-            metro_button(ui, "Friend Request", Some(("", 24.0)));
+        let (status, they_accepted) = if let Some(contact) = CONTACTS_LIST.lock().get(&id) {
+            (contact.contact_status, contact.is_accepted)
+        } else { (ContactStatus::None, false) };
+
+        match status {
+            ContactStatus::Requested => {
+                if metro_button(ui, "Accept friend request", Some(("", 24.0))).clicked() {
+                    self.send_contact_action(&id, &name, ContactAction::Accept);
+                }
+                if metro_button(ui, "Ignore friend request", None).clicked() {
+                    self.send_contact_action(&id, &name, ContactAction::Ignore);
+                }
+            },
+            ContactStatus::Accepted => {
+                let label = if they_accepted { "Remove friend" } else { "Cancel friend request" };
+                if metro_button(ui, label, None).clicked() {
+                    self.send_contact_action(&id, &name, ContactAction::Remove);
+                }
+            },
+            ContactStatus::Blocked => {},
+            _ => {
+                if metro_button(ui, "Friend Request", Some(("", 24.0))).clicked() {
+                    self.send_contact_action(&id, &name, ContactAction::Add);
+                }
+            },
+        }
+
+        if status == ContactStatus::Blocked {
+            if metro_button(ui, "Unblock", None).clicked() {
+                self.send_contact_action(&id, &name, ContactAction::Remove);
+            }
+        } else if metro_button(ui, "Block", None).clicked() {
+            self.send_contact_action(&id, &name, ContactAction::Block);
         }
-        metro_button(ui, "Block", None);
 
 
         // this is here for debug purposes, it's useful so i'm going to leave it here
@@ -130,6 +163,9 @@ impl TemplateApp {
                 ui.label(format!("{}hash not in table", if active {"Active - "} else {""}));
             }
         }
-        
+    }
+
+    fn send_contact_action(&mut self, id: &str, username: &str, action: ContactAction) {
+        self.backend.tx.send(UiToReso::SignalUpdateContact(id.to_owned(), username.to_owned(), action)).unwrap();
     }
 }
\ No newline at end of file
@@ -0,0 +1,33 @@
+use egui::{vec2, Color32, FontId, Rounding};
+
+use crate::{api::client::Record, widgets::{loadable_image::{image_retry, loadable_image}, page_header::page_header, record_card::record_details}, TemplateApp};
+
+impl TemplateApp {
+    /// Shared item opened from a resrec link, big thumbnail with the same details the popover has
+    pub fn record_page(&mut self, ui: &mut egui::Ui, record: Record) {
+        page_header(ui, "Item", &record.name);
+
+        egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).show(ui, |ui| {
+            let left = ui.spacing().window_margin.left + 10.0;
+
+            let size = (ui.available_width() - left - ui.spacing().window_margin.right).min(320.0);
+            let (mut rect, _) = ui.allocate_exact_size(vec2(ui.available_width(), size), egui::Sense::hover());
+            rect.min.x += left;
+            rect.max.x = rect.min.x + size;
+            if let Some(uri) = &record.thumbnail_uri {
+                let loadable = self.image_cache.get_image(uri);
+                loadable_image(ui, &loadable, rect, "", Color32::from_gray(34), 0.0, false);
+                image_retry(ui, &loadable, rect);
+            } else {
+                ui.painter().rect_filled(rect, Rounding::same(0.0), Color32::from_gray(34));
+                ui.painter().text(rect.center(), egui::Align2::CENTER_CENTER, "", FontId::proportional(96.0), Color32::WHITE);
+            }
+
+            ui.add_space(16.0);
+            ui.horizontal(|ui| {
+                ui.add_space(left);
+                ui.vertical(|ui| record_details(ui, &record));
+            });
+        });
+    }
+}
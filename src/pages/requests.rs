@@ -0,0 +1,73 @@
+use egui::{vec2, Align2, Color32, FontId};
+
+use crate::{api::client::{Contact, ContactAction}, backend::thread::UiToReso, widgets::{button::metro_button, page_header::page_header, user_info::{user_info_widget, UserInfoVariant}}, FrontendPage, TemplateApp, CONTACTS_LIST, SUBHEADER_COL};
+
+impl TemplateApp {
+    pub fn friend_requests_page(&mut self, ui: &mut egui::Ui) {
+        let (incoming_count, outgoing_count) = self.friend_request_counts();
+        page_header(ui, "Friend Requests", &format!("{} received, {} sent", incoming_count, outgoing_count));
+
+        // cloned so we're not holding the lock while the buttons send stuff off
+        let (mut incoming, mut outgoing): (Vec<Contact>, Vec<Contact>) = {
+            let contacts = CONTACTS_LIST.lock();
+            (
+                contacts.values().filter(|c| c.is_incoming_request()).cloned().collect(),
+                contacts.values().filter(|c| c.is_outgoing_request()).cloned().collect(),
+            )
+        };
+        incoming.sort_by(|a, b| a.contact_username.to_lowercase().cmp(&b.contact_username.to_lowercase()));
+        outgoing.sort_by(|a, b| a.contact_username.to_lowercase().cmp(&b.contact_username.to_lowercase()));
+
+        egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).show(ui, |ui| {
+            ui.style_mut().spacing.item_spacing.y = 4.0;
+
+            if incoming.is_empty() && outgoing.is_empty() {
+                let (response, painter) = ui.allocate_painter(vec2(ui.available_width(), 60.0), egui::Sense::hover());
+                painter.text(response.rect.left_center() + vec2(ui.style().spacing.window_margin.left, 0.0), Align2::LEFT_CENTER, "No pending requests", FontId::proportional(24.0), SUBHEADER_COL);
+                return;
+            }
+
+            if !incoming.is_empty() {
+                section_label(ui, "Received");
+                for contact in &incoming {
+                    if self.request_row(ui, contact) {
+                        self.set_page(FrontendPage::ProfilePage(contact.id.clone()));
+                    }
+                    ui.columns(2, |cols| {
+                        cols[0].style_mut().spacing.interact_size.y = 60.0;
+                        cols[1].style_mut().spacing.interact_size.y = 60.0;
+                        if metro_button(&mut cols[0], "Accept", Some(("", 24.0))).clicked() {
+                            self.backend.tx.send(UiToReso::SignalUpdateContact(contact.id.clone(), contact.contact_username.clone(), ContactAction::Accept)).unwrap();
+                        }
+                        if metro_button(&mut cols[1], "Decline", Some(("", 24.0))).clicked() {
+                            self.backend.tx.send(UiToReso::SignalUpdateContact(contact.id.clone(), contact.contact_username.clone(), ContactAction::Ignore)).unwrap();
+                        }
+                    });
+                }
+            }
+
+            if !outgoing.is_empty() {
+                section_label(ui, "Sent");
+                for contact in &outgoing {
+                    if self.request_row(ui, contact) {
+                        self.set_page(FrontendPage::ProfilePage(contact.id.clone()));
+                    }
+                    ui.style_mut().spacing.interact_size.y = 60.0;
+                    if metro_button(ui, "Cancel request", Some(("", 24.0))).clicked() {
+                        self.backend.tx.send(UiToReso::SignalUpdateContact(contact.id.clone(), contact.contact_username.clone(), ContactAction::Remove)).unwrap();
+                    }
+                }
+            }
+        });
+    }
+
+    fn request_row(&mut self, ui: &mut egui::Ui, contact: &Contact) -> bool {
+        ui.style_mut().spacing.interact_size.y = 104.0;
+        user_info_widget(ui, &mut self.image_cache, UserInfoVariant::Contact(contact)).clicked()
+    }
+}
+
+fn section_label(ui: &mut egui::Ui, text: &str) {
+    let (response, painter) = ui.allocate_painter(vec2(ui.available_width(), 48.0), egui::Sense::hover());
+    painter.text(response.rect.left_bottom() + vec2(ui.style().spacing.window_margin.left, -8.0), Align2::LEFT_BOTTOM, text, FontId::proportional(20.0), Color32::GRAY);
+}
@@ -1,6 +1,6 @@
-use egui::{vec2, Align2, Color32, Pos2, Rect, Rounding, Stroke};
+use egui::{vec2, Align2, Color32, Pos2, Rect, RichText, Rounding, Stroke};
 
-use crate::{widgets::page_header::page_header, TemplateApp, SESSION_CACHE};
+use crate::{backend::thread::SessionUpdate, widgets::{button::metro_button, loadable_image::{image_retry, loadable_image}, page_header::page_header}, FrontendPage, TemplateApp, SESSION_CACHE, SUBHEADER_COL};
 
 impl TemplateApp {
     pub fn sessions_page(&mut self, ui: &mut egui::Ui) {
@@ -8,12 +8,16 @@ impl TemplateApp {
 
         let sessions = SESSION_CACHE.lock();
 
+        let mut open = None;
         egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).show(ui, |ui| {
             for (id, session) in sessions.iter() {
                 
                 let (response, painter) = ui.allocate_painter(vec2(ui.available_width(), 104.0), egui::Sense::click());
 
                 if !ui.is_rect_visible(response.rect) { continue; }
+                if response.clicked() {
+                    open = Some(id.clone());
+                }
 
                 if response.is_pointer_button_down_on() {
                     painter.rect_filled(response.rect, Rounding::same(0.0), ui.style().visuals.widgets.active.bg_fill);
@@ -36,5 +40,74 @@ impl TemplateApp {
                 painter.circle(img_rect.center(), img_rect.width() / 2.0, Color32::RED, Stroke::NONE);
             }
         });
+        drop(sessions);
+        if let Some(id) = open {
+            self.set_page(FrontendPage::SessionPage(id, None));
+        }
+    }
+
+    /// `invite` is what to show if the live list doesn't have it, it might be out of date
+    pub fn session_page(&mut self, ui: &mut egui::Ui, id: String, invite: Option<SessionUpdate>) {
+        let Some(session) = SESSION_CACHE.lock().get(&id).cloned().or(invite) else {
+            page_header(ui, "Session", "This session isn't around anymore");
+            return;
+        };
+
+        page_header(ui, &session.name, &format!("Hosted by {}", session.host_username));
+
+        egui::containers::ScrollArea::vertical().scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden).show(ui, |ui| {
+            ui.style_mut().spacing.item_spacing.y = 8.0;
+            let left = ui.spacing().window_margin.left + 10.0;
+
+            if let Some(url) = &session.thumbnail_url {
+                let width = (ui.available_width() - left - ui.spacing().window_margin.right).min(640.0);
+                let (mut rect, _) = ui.allocate_exact_size(vec2(ui.available_width(), width * 9.0 / 16.0), egui::Sense::hover());
+                rect.min.x += left;
+                rect.max.x = rect.min.x + width;
+                let loadable = self.image_cache.get_image(url);
+                loadable_image(ui, &loadable, rect, "", Color32::from_gray(34), 0.0, false);
+                image_retry(ui, &loadable, rect);
+            }
+
+            let users = if session.has_ended {
+                "This session has ended".to_owned()
+            } else {
+                format!("{}/{} users, {} active", session.joined_users, session.max_users, session.active_users)
+            };
+            let mut lines = vec![
+                (users, Color32::WHITE),
+                (format!("Started {}", self.time_format.date_time(&session.session_begin_time.0)), SUBHEADER_COL),
+            ];
+            if session.headless_host {
+                lines.push(("Headless".to_owned(), SUBHEADER_COL));
+            }
+            if session.mobile_friendly {
+                lines.push(("Mobile friendly".to_owned(), SUBHEADER_COL));
+            }
+            if let Some(description) = &session.description {
+                if !description.is_empty() {
+                    lines.push((description.clone(), Color32::WHITE));
+                }
+            }
+            if !session.tags.is_empty() {
+                let mut tags: Vec<&String> = session.tags.iter().collect();
+                tags.sort();
+                lines.push((tags.iter().map(|t| t.as_str()).collect::<Vec<&str>>().join(", "), SUBHEADER_COL));
+            }
+
+            for (text, col) in lines {
+                ui.horizontal(|ui| {
+                    ui.add_space(left);
+                    ui.add(egui::Label::new(RichText::new(text).size(20.0).color(col)).wrap());
+                });
+            }
+
+            if let Some(host_id) = &session.host_user_id {
+                ui.style_mut().spacing.interact_size.y = 60.0;
+                if metro_button(ui, "View host", Some(("", 24.0))).clicked() {
+                    self.set_page(FrontendPage::ProfilePage(host_id.clone()));
+                }
+            }
+        });
     }
 }
\ No newline at end of file
@@ -0,0 +1,403 @@
+use std::{ops::Range, sync::atomic::{AtomicBool, Ordering}};
+
+use egui::{text::LayoutJob, Color32, FontFamily, FontId, Galley, Stroke, TextFormat, Vec2};
+
+/// Biggest and smallest `<size>` we'll go to, so nobody can blow a bubble up to fill the screen
+const MIN_SIZE: f32 = 8.0;
+const MAX_SIZE: f32 = 96.0;
+/// Font family `<b>` uses, main.rs registers it as Segoe UI Bold if the system has it and regular Segoe UI if not
+pub const BOLD_FAMILY: &str = "Bold";
+/// Whether `BOLD_FAMILY` is actually bold, if it isn't we space the letters out a bit so it still stands out
+static BOLD_FACE: AtomicBool = AtomicBool::new(false);
+/// Extra letter spacing for fake bold, as a fraction of the font size
+const BOLD_SPACING: f32 = 0.06;
+
+/// What counts as a link when it shows up in a message
+const LINK_SCHEMES: [&str; 4] = ["https://", "http://", "resrec:///", "ressession:///"];
+const LINK_COL: Color32 = Color32::from_rgb(102, 178, 255);
+
+/// A clickable part of a laid out message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// Character (not byte) range in the job's text, which is what galley cursors count in
+    pub chars: Range<usize>,
+    pub target: String,
+}
+
+/// One piece of Unity-style rich text, as much as we understand of it
+#[derive(Debug, Clone, PartialEq)]
+enum Token<'a> {
+    Text(&'a str),
+    Open(Tag, Option<&'a str>),
+    Close(Tag),
+    Break,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tag {
+    Bold,
+    Italic,
+    Underline,
+    Strike,
+    Color,
+    Size,
+    Mark,
+    /// custom emoji, we've got nothing to draw them with so they're dropped
+    Sprite,
+}
+
+impl Tag {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "b" => Some(Tag::Bold),
+            "i" => Some(Tag::Italic),
+            "u" => Some(Tag::Underline),
+            "s" => Some(Tag::Strike),
+            "color" => Some(Tag::Color),
+            "size" => Some(Tag::Size),
+            "mark" => Some(Tag::Mark),
+            "sprite" => Some(Tag::Sprite),
+            _ => None,
+        }
+    }
+}
+
+/// Splits text into tokens. Anything that doesn't look like a tag we know stays as text,
+/// so a stray `<` or `<3` comes out exactly as typed
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start..].find('>') else { break };
+        let inner = &rest[start + 1..start + len];
+
+        // `<3 <b>`, the first one's just a less-than
+        if let Some(next) = inner.find('<') {
+            tokens.push(Token::Text(&rest[..start + 1 + next]));
+            rest = &rest[start + 1 + next..];
+            continue;
+        }
+
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        let after = &rest[start + len + 1..];
+
+        // everything up to the closing noparse is literal, tags and all
+        if inner.eq_ignore_ascii_case("noparse") {
+            let lower = after.to_ascii_lowercase();
+            match lower.find("</noparse>") {
+                Some(end) => {
+                    tokens.push(Token::Text(&after[..end]));
+                    rest = &after[end + "</noparse>".len()..];
+                },
+                None => {
+                    tokens.push(Token::Text(after));
+                    rest = "";
+                },
+            }
+            continue;
+        }
+
+        match parse_tag(inner) {
+            Some(token) => tokens.push(token),
+            None => tokens.push(Token::Text(&rest[start..start + len + 1])),
+        }
+        rest = after;
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+/// What's between the angle brackets, e.g. `color=#f00`, `/b`, `sprite name="heart"`
+fn parse_tag(inner: &str) -> Option<Token<'_>> {
+    // `a < b > c` is maths, not a tag
+    if inner.starts_with(char::is_whitespace) { return None; }
+    let inner = inner.trim_end();
+    if inner.eq_ignore_ascii_case("br") || inner.eq_ignore_ascii_case("br/") {
+        return Some(Token::Break);
+    }
+    if let Some(name) = inner.strip_prefix('/') {
+        return Tag::from_name(name.trim()).map(Token::Close);
+    }
+
+    let name_end = inner.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(inner.len());
+    let tag = Tag::from_name(&inner[..name_end])?;
+    let value = inner[name_end..].trim_start().strip_prefix('=')
+        .map(|v| v.trim().trim_matches(|c| c == '"' || c == '\''));
+    Some(Token::Open(tag, value))
+}
+
+/// `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, or one of the names Unity knows
+fn parse_color(value: &str) -> Option<Color32> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let digit = |i: usize, len: usize| u8::from_str_radix(hex.get(i..i + len)?, 16).ok();
+        return match hex.len() {
+            3 | 4 => {
+                let short = |i| digit(i, 1).map(|d| d * 17);
+                let a = if hex.len() == 4 { short(3)? } else { 255 };
+                Some(Color32::from_rgba_unmultiplied(short(0)?, short(1)?, short(2)?, a))
+            },
+            6 | 8 => {
+                let a = if hex.len() == 8 { digit(6, 2)? } else { 255 };
+                Some(Color32::from_rgba_unmultiplied(digit(0, 2)?, digit(2, 2)?, digit(4, 2)?, a))
+            },
+            _ => None,
+        };
+    }
+
+    match value.to_ascii_lowercase().as_str() {
+        "black" => Some(Color32::BLACK),
+        "white" => Some(Color32::WHITE),
+        "red" => Some(Color32::RED),
+        "green" => Some(Color32::from_rgb(0, 128, 0)),
+        "lime" => Some(Color32::GREEN),
+        "blue" => Some(Color32::BLUE),
+        "yellow" => Some(Color32::YELLOW),
+        "cyan" | "aqua" => Some(Color32::from_rgb(0, 255, 255)),
+        "magenta" | "fuchsia" => Some(Color32::from_rgb(255, 0, 255)),
+        "orange" => Some(Color32::from_rgb(255, 165, 0)),
+        "purple" => Some(Color32::from_rgb(128, 0, 128)),
+        "brown" => Some(Color32::from_rgb(165, 42, 42)),
+        "gray" | "grey" => Some(Color32::GRAY),
+        "clear" => Some(Color32::TRANSPARENT),
+        _ => None,
+    }
+}
+
+/// `50`, `+10`, `-4`, `150%`. Relative ones are relative to whatever size we're at now
+fn parse_size(value: &str, current: f32) -> Option<f32> {
+    let value = value.trim().trim_end_matches("px");
+    let size = if let Some(percent) = value.strip_suffix('%') {
+        current * percent.parse::<f32>().ok()? / 100.0
+    } else if value.starts_with('+') || value.starts_with('-') {
+        current + value.parse::<f32>().ok()?
+    } else {
+        value.parse::<f32>().ok()?
+    };
+    Some(size.clamp(MIN_SIZE, MAX_SIZE))
+}
+
+/// main.rs tells us whether it found a real bold face
+pub fn set_bold_face(loaded: bool) {
+    BOLD_FACE.store(loaded, Ordering::Relaxed);
+}
+
+/// Byte ranges of anything in `text` that starts with one of `LINK_SCHEMES`, up to the next space.
+/// Trailing punctuation's left off since it's almost always the sentence, not the link
+fn find_links(text: &str) -> Vec<Range<usize>> {
+    let mut links = Vec::new();
+    let mut from = 0;
+    while let Some((start, scheme)) = LINK_SCHEMES.iter()
+        .filter_map(|scheme| text[from..].find(scheme).map(|pos| (from + pos, scheme)))
+        .min_by_key(|(pos, _)| *pos)
+    {
+        let len = text[start..].find(char::is_whitespace).unwrap_or(text.len() - start);
+        let link = text[start..start + len].trim_end_matches(|c| matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']' | '\'' | '"'));
+        if link.len() > scheme.len() {
+            links.push(start..start + link.len());
+        }
+        from = start + len.max(scheme.len());
+    }
+    links
+}
+
+/// Appends `text`, splitting links out into their own underlined sections
+fn append_with_links(job: &mut LayoutJob, links: &mut Vec<Link>, text: &str, format: &TextFormat) {
+    let mut last = 0;
+    for range in find_links(text) {
+        job.append(&text[last..range.start], 0.0, format.clone());
+
+        let start = job.text.chars().count();
+        let link_format = TextFormat { color: LINK_COL, underline: Stroke::new(1.0, LINK_COL), ..format.clone() };
+        job.append(&text[range.clone()], 0.0, link_format);
+        links.push(Link { chars: start..job.text.chars().count(), target: text[range.clone()].to_owned() });
+
+        last = range.end;
+    }
+    job.append(&text[last..], 0.0, format.clone());
+}
+
+/// The link under `pos`, which is relative to where the galley was drawn
+pub fn link_at<'a>(galley: &Galley, links: &'a [Link], pos: Vec2) -> Option<&'a Link> {
+    if !galley.rect.contains(pos.to_pos2()) { return None; }
+    // the cursor lands between characters, so check the one either side of it
+    let idx = galley.cursor_from_pos(pos).ccursor.index;
+    links.iter().find(|link| link.chars.contains(&idx) || (idx > 0 && link.chars.end == idx))
+}
+
+/// Resonite rich text laid out for egui, plus where the links ended up. Unknown or broken tags show up as plain text.
+/// `<b>` uses `BOLD_FAMILY`, and brightens text that's still in the base colour
+pub fn rich_text_job(text: &str, size: f32, color: Color32, wrap_width: f32) -> (LayoutJob, Vec<Link>) {
+    let base = TextFormat { font_id: FontId::proportional(size), color, ..Default::default() };
+
+    let mut job = LayoutJob::default();
+    job.wrap.max_width = wrap_width;
+    let mut links = Vec::new();
+
+    // each open tag remembers the format from before it, closing it puts that back
+    let mut stack: Vec<(Tag, TextFormat)> = Vec::new();
+    let mut format = base.clone();
+
+    for token in tokenize(text) {
+        match token {
+            Token::Text(text) => append_with_links(&mut job, &mut links, text, &format),
+            Token::Break => job.append("\n", 0.0, format.clone()),
+            Token::Open(Tag::Sprite, _) | Token::Close(Tag::Sprite) => {},
+            Token::Open(tag, value) => {
+                stack.push((tag, format.clone()));
+                match tag {
+                    Tag::Bold => {
+                        format.font_id.family = FontFamily::Name(BOLD_FAMILY.into());
+                        if !BOLD_FACE.load(Ordering::Relaxed) {
+                            format.extra_letter_spacing += format.font_id.size * BOLD_SPACING;
+                        }
+                        // anything <color> set wins
+                        if format.color == color { format.color = Color32::WHITE }
+                    },
+                    Tag::Italic => format.italics = true,
+                    Tag::Underline => format.underline = Stroke::new(1.0, format.color),
+                    Tag::Strike => format.strikethrough = Stroke::new(1.0, format.color),
+                    Tag::Color => if let Some(col) = value.and_then(parse_color) { format.color = col },
+                    Tag::Size => if let Some(size) = value.and_then(|v| parse_size(v, format.font_id.size)) { format.font_id.size = size },
+                    Tag::Mark => if let Some(col) = value.and_then(parse_color) { format.background = col },
+                    Tag::Sprite => {},
+                }
+            },
+            Token::Close(tag) => {
+                // unity lets you close things out of order, so unwind to the newest matching tag
+                if let Some(idx) = stack.iter().rposition(|(open, _)| *open == tag) {
+                    format = stack[idx].1.clone();
+                    stack.truncate(idx);
+                }
+            },
+        }
+    }
+    (job, links)
+}
+
+/// Just the text, for previews and anywhere else formatting doesn't fit
+pub fn strip_tags(text: &str) -> String {
+    tokenize(text).into_iter().map(|token| match token {
+        Token::Text(text) => text,
+        Token::Break => " ",
+        _ => "",
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Format of whichever section starts with `needle`
+    fn format_of<'a>(job: &'a LayoutJob, needle: &str) -> &'a TextFormat {
+        let start = job.text.find(needle).expect("needle not in text");
+        &job.sections.iter().find(|s| s.byte_range.start == start).expect("no section starts there").format
+    }
+
+    fn job(text: &str) -> LayoutJob {
+        rich_text_job(text, 24.0, Color32::GRAY, 400.0).0
+    }
+
+    #[test]
+    fn heart_then_tag() {
+        assert_eq!(tokenize("<3 <b>hi"), vec![Token::Text("<3 "), Token::Open(Tag::Bold, None), Token::Text("hi")]);
+        let job = job("<3 <b>hi");
+        assert_eq!(job.text, "<3 hi");
+        assert_eq!(format_of(&job, "hi").color, Color32::WHITE);
+    }
+
+    #[test]
+    fn stray_brackets_stay_literal() {
+        assert_eq!(job("a < b > c").text, "a < b > c");
+        assert_eq!(job("1 < 2").text, "1 < 2");
+        assert_eq!(job("<wave>hi</wave>").text, "<wave>hi</wave>");
+        assert_eq!(job("<b").text, "<b");
+    }
+
+    #[test]
+    fn unclosed_tags_run_to_the_end() {
+        let job = job("<i>one <u>two");
+        assert_eq!(job.text, "one two");
+        assert!(format_of(&job, "two").italics);
+        assert_ne!(format_of(&job, "two").underline, Stroke::NONE);
+    }
+
+    #[test]
+    fn closing_out_of_order_unwinds() {
+        let job = job("<b><i>x</b>y");
+        assert_eq!(job.text, "xy");
+        assert!(format_of(&job, "x").italics);
+        // closing b closed the i opened inside it too
+        assert!(!format_of(&job, "y").italics);
+        assert_eq!(format_of(&job, "y").color, Color32::GRAY);
+    }
+
+    #[test]
+    fn stray_close_is_ignored() {
+        let job = job("a</b>b");
+        assert_eq!(job.text, "ab");
+    }
+
+    #[test]
+    fn noparse_keeps_tags_literal() {
+        assert_eq!(job("<noparse><b>x</b></noparse> <b>y</b>").text, "<b>x</b> y");
+        assert_eq!(job("<NOPARSE><i>forever").text, "<i>forever");
+    }
+
+    #[test]
+    fn colour_survives_bold() {
+        let job = job("<color=#f00><b>hi</b></color>");
+        assert_eq!(format_of(&job, "hi").color, Color32::from_rgb(255, 0, 0));
+        assert_eq!(format_of(&job, "hi").font_id.family, FontFamily::Name(BOLD_FAMILY.into()));
+    }
+
+    #[test]
+    fn bold_keeps_the_size() {
+        let job = job("a<b>b</b>");
+        assert_eq!(format_of(&job, "b").font_id.size, 24.0);
+    }
+
+    #[test]
+    fn colours() {
+        assert_eq!(parse_color("#f00"), Some(Color32::from_rgb(255, 0, 0)));
+        assert_eq!(parse_color("#00ff0080"), Some(Color32::from_rgba_unmultiplied(0, 255, 0, 128)));
+        assert_eq!(parse_color("Red"), Some(Color32::RED));
+        assert_eq!(parse_color("#ff"), None);
+        assert_eq!(parse_color("#ggg"), None);
+        assert_eq!(parse_color("mauve"), None);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("50", 24.0), Some(50.0));
+        assert_eq!(parse_size("+6", 24.0), Some(30.0));
+        assert_eq!(parse_size("50%", 24.0), Some(12.0));
+        assert_eq!(parse_size("9000", 24.0), Some(MAX_SIZE));
+        assert_eq!(parse_size("huge", 24.0), None);
+    }
+
+    #[test]
+    fn links() {
+        let text = "see https://resonite.com. or (resrec:///U-a/R-b) and http:// alone";
+        let found: Vec<&str> = find_links(text).into_iter().map(|r| &text[r]).collect();
+        assert_eq!(found, vec!["https://resonite.com", "resrec:///U-a/R-b"]);
+    }
+
+    #[test]
+    fn link_ranges_count_chars() {
+        let (job, links) = rich_text_job("héllo https://a.b", 24.0, Color32::GRAY, 400.0);
+        assert_eq!(links.len(), 1);
+        let chars: String = job.text.chars().skip(links[0].chars.start).take(links[0].chars.len()).collect();
+        assert_eq!(chars, "https://a.b");
+    }
+
+    #[test]
+    fn strip() {
+        assert_eq!(strip_tags("<b>hi</b><br>there <3"), "hi there <3");
+    }
+}
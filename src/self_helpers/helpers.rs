@@ -1,4 +1,4 @@
-use crate::TemplateApp;
+use crate::{icon_notification, FrontendPage, TemplateApp, CONTACTS_LIST, KEYRING_SERVICE, KEYRING_USER, MESSAGE_CACHE, SESSION_CACHE};
 
 impl TemplateApp {
 
@@ -10,6 +10,90 @@ impl TemplateApp {
         }
     }
 
+    /// Drops everything tied to the signed in account and goes back to the sign in page, without restarting
+    pub fn reset_session(&mut self) {
+        self.logged_in = false;
+        self.can_attempt_login = true;
+        self.you = None;
+        self.user_id = None;
+        self.token.clear();
+        self.cached_user_infos.clear();
+        self.history_loading.clear();
+        self.history_exhausted.clear();
+        self.history_failed.clear();
+        self.history_hold.clear();
+        self.history_anchor.clear();
+        // ones that link somewhere (messages, invites) point at the old account's stuff, errors can stay
+        self.notifications.retain(|notification| notification.action.is_none());
+        self.entry_fields.user_info_query_results.clear();
+        self.entry_fields.message_buffer.clear();
+        self.entry_fields.login_details.password.clear();
+        self.entry_fields.login_details.needs_totp = false;
+        self.entry_fields.login_details.totp.clear();
+
+        // the stored token is dead now, no point keeping it
+        if let Ok(entry) = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER) {
+            if let Err(err) = entry.delete_password() {
+                if !matches!(err, keyring::Error::NoEntry) {
+                    self.notifications.push(icon_notification("", "Keyring deletion failed", format!("{}", err).as_str()));
+                }
+            }
+        }
+
+        self.page_stack.clear();
+        self.page_stack.push(FrontendPage::SignInPage);
+        self.current_page = 0;
+    }
+
+    /// (incoming, outgoing) pending friend requests
+    pub fn friend_request_counts(&self) -> (usize, usize) {
+        let contacts = CONTACTS_LIST.lock();
+        let incoming = contacts.values().filter(|c| c.is_incoming_request()).count();
+        let outgoing = contacts.values().filter(|c| c.is_outgoing_request()).count();
+        (incoming, outgoing)
+    }
+
+    /// Unread messages across every conversation, minus anyone blocked since those aren't shown
+    pub fn unread_message_count(&self) -> usize {
+        let contacts = CONTACTS_LIST.lock();
+        MESSAGE_CACHE.lock().iter()
+            .filter(|(id, _)| !contacts.get(*id).map_or(false, |c| c.is_blocked()))
+            .map(|(_, conversation)| conversation.unread_count())
+            .sum()
+    }
+
+    /// Something clicked in a message. Web links go to the browser (asking first if that's on),
+    /// Resonite ones open in here if we know what they point at, otherwise they're copied so they can go in the game
+    pub fn open_link(&mut self, ctx: &egui::Context, target: &str) {
+        if target.starts_with("http://") || target.starts_with("https://") {
+            if self.confirm_links {
+                self.pending_link = Some(target.to_owned());
+            } else {
+                ctx.open_url(egui::OpenUrl::new_tab(target));
+            }
+            return;
+        }
+
+        if let Some(session_id) = target.strip_prefix("ressession:///") {
+            let known = SESSION_CACHE.lock().values()
+                .find(|s| s.session_id == session_id || s.session_urls.iter().any(|url| url == target))
+                .map(|s| s.session_id.clone());
+            if let Some(id) = known {
+                self.set_page(FrontendPage::SessionPage(id, None));
+                return;
+            }
+        } else if target.starts_with("resrec:///") {
+            let record = MESSAGE_CACHE.lock().find_record(target);
+            if let Some(record) = record {
+                self.set_page(FrontendPage::RecordPage(Box::new(record)));
+                return;
+            }
+        }
+
+        ctx.copy_text(target.to_owned());
+        self.notifications.push(icon_notification("", "Link copied", target));
+    }
+
     pub fn /*baba_*/is_you(&self, id: &String) -> bool {
         if let Some(you_id) = &self.user_id {
             you_id.eq(id)
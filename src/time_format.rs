@@ -0,0 +1,134 @@
+use std::env;
+
+use chrono::{DateTime, Datelike, Local, Utc};
+
+/// Which way round the numbers in a date go
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum DateOrder {
+    /// 6/9/2015, the american one
+    MonthDayYear,
+    /// 9/6/2015
+    DayMonthYear,
+    /// 2015/6/9
+    YearMonthDay,
+}
+
+impl DateOrder {
+    pub const ALL: [DateOrder; 3] = [DateOrder::MonthDayYear, DateOrder::DayMonthYear, DateOrder::YearMonthDay];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DateOrder::MonthDayYear => "Month/Day/Year",
+            DateOrder::DayMonthYear => "Day/Month/Year",
+            DateOrder::YearMonthDay => "Year/Month/Day",
+        }
+    }
+
+    /// The one after this, wrapping around. For the settings button
+    pub fn next(&self) -> Self {
+        let idx = Self::ALL.iter().position(|o| o == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+/// How every date and time in the app gets shown. Everything's converted to the system's local timezone first
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct TimeFormat {
+    pub date_order: DateOrder,
+    pub twenty_four_hour: bool,
+}
+
+impl Default for TimeFormat {
+    /// Best guess from the system locale, there's no portable way to get the OS's actual date and time settings
+    fn default() -> Self {
+        let locale = system_locale();
+        let (language, region) = match locale.split_once('_') {
+            Some((language, region)) => (language.to_owned(), region.to_owned()),
+            None => (locale.clone(), String::new()),
+        };
+
+        let date_order = match (language.as_str(), region.as_str()) {
+            ("en", "US") | ("en", "PH") | ("", _) | ("c", _) | ("posix", _) => DateOrder::MonthDayYear,
+            ("zh", _) | ("ja", _) | ("ko", _) | ("hu", _) | ("lt", _) | ("mn", _) | ("en", "CA") => DateOrder::YearMonthDay,
+            _ => DateOrder::DayMonthYear,
+        };
+        let twelve_hour = matches!((language.as_str(), region.as_str()),
+            ("en", "US") | ("en", "CA") | ("en", "AU") | ("en", "NZ") | ("en", "PH") | ("en", "IN") | ("", _) | ("c", _) | ("posix", _)
+        );
+
+        Self { date_order, twenty_four_hour: !twelve_hour }
+    }
+}
+
+/// e.g. `en_US`, lowercased language, uppercased region, encoding and modifiers cut off. Empty if nothing's set.
+/// Asks the OS (windows doesn't have the env vars at all), on unix LC_ALL/LC_TIME still win since they're the time specific ones
+fn system_locale() -> String {
+    let from_env = |vars: &[&str]| vars.iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|val| !val.is_empty());
+    let raw = if cfg!(unix) { from_env(&["LC_ALL", "LC_TIME"]) } else { None }
+        .or_else(sys_locale::get_locale)
+        .or_else(|| if cfg!(unix) { from_env(&["LANG"]) } else { None })
+        .unwrap_or_default();
+    let raw = raw.split(['.', '@']).next().unwrap_or_default();
+    match raw.split_once(['_', '-']) {
+        Some((language, region)) => format!("{}_{}", language.to_lowercase(), region.to_uppercase()),
+        None => raw.to_lowercase(),
+    }
+}
+
+impl TimeFormat {
+    /// Just the numbers, e.g. 6/9/2015
+    pub fn date(&self, time: &DateTime<Utc>) -> String {
+        let local = time.with_timezone(&Local);
+        match self.date_order {
+            DateOrder::MonthDayYear => format!("{}/{}/{}", local.month(), local.day(), local.year()),
+            DateOrder::DayMonthYear => format!("{}/{}/{}", local.day(), local.month(), local.year()),
+            DateOrder::YearMonthDay => format!("{}/{}/{}", local.year(), local.month(), local.day()),
+        }
+    }
+
+    /// 3:04 PM, or 15:04
+    pub fn time(&self, time: &DateTime<Utc>) -> String {
+        let local = time.with_timezone(&Local);
+        if self.twenty_four_hour {
+            local.format("%H:%M").to_string()
+        } else {
+            local.format("%-I:%M %p").to_string()
+        }
+    }
+
+    pub fn date_time(&self, time: &DateTime<Utc>) -> String {
+        format!("{} {}", self.date(time), self.time(time))
+    }
+
+    /// For lists, "Just now", "3 min ago", today's time, "Yesterday", a weekday, then the full date
+    pub fn relative(&self, time: &DateTime<Utc>) -> String {
+        let since = Utc::now().signed_duration_since(*time);
+        if since.num_seconds() < 60 {
+            // also catches times a bit in the future, from clocks being off
+            "Just now".to_owned()
+        } else if since.num_minutes() < 60 {
+            format!("{} min ago", since.num_minutes())
+        } else {
+            self.day_or(time, |format| format.time(time))
+        }
+    }
+
+    /// For date separators, "Today", "Yesterday", a weekday, then the full date
+    pub fn day_label(&self, time: &DateTime<Utc>) -> String {
+        self.day_or(time, |_| "Today".to_owned())
+    }
+
+    /// "Yesterday" and weekdays for the last week, full date before that, `today` for today
+    fn day_or(&self, time: &DateTime<Utc>, today: impl FnOnce(&Self) -> String) -> String {
+        let day = time.with_timezone(&Local).date_naive();
+        let days_ago = Local::now().date_naive().signed_duration_since(day).num_days();
+        match days_ago {
+            ..=0 => today(self),
+            1 => "Yesterday".to_owned(),
+            2..=6 => time.with_timezone(&Local).format("%A").to_string(),
+            _ => self.date(time),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use egui::{pos2, vec2, Align2, Color32, FontId, Rect, Rounding, Sense};
+
+use crate::{audio::LoadableAudio, HOVER_COL, SUBHEADER_COL};
+use super::segoe_boot_spinner::SegoeBootSpinner;
+
+/// Mic icon, waveform, duration, all on one line
+pub const AUDIO_CARD_SIZE: egui::Vec2 = vec2(320.0, 56.0);
+
+/// Draws a voice message into `rect`. Clicking it is up to the caller
+pub fn audio_card(ui: &mut egui::Ui, rect: Rect, clip: &LoadableAudio, id: egui::Id) -> egui::Response {
+    let response = ui.interact(rect, id, Sense::click());
+    if response.hovered() {
+        ui.painter().rect_filled(rect.expand(4.0), Rounding::same(0.0), HOVER_COL);
+    }
+
+    let icon_rect = Rect::from_min_size(rect.min, vec2(rect.height(), rect.height()));
+    ui.painter().text(icon_rect.center(), Align2::CENTER_CENTER, "", FontId::proportional(32.0), Color32::WHITE);
+
+    let mut wave_rect = rect;
+    wave_rect.min.x = icon_rect.max.x + 8.0;
+
+    match clip {
+        LoadableAudio::Unloaded | LoadableAudio::Loading => {
+            let spinner_rect = Rect::from_center_size(wave_rect.center(), vec2(32.0, 32.0));
+            SegoeBootSpinner::new().size(16.0).paint_at(ui, spinner_rect);
+        },
+        LoadableAudio::Failed(_) => {
+            ui.painter().text(wave_rect.left_center(), Align2::LEFT_CENTER, "Couldn't load audio", FontId::proportional(20.0), SUBHEADER_COL);
+        },
+        LoadableAudio::Loaded(clip) => {
+            let duration = clip.duration_label();
+            let d_galley = ui.painter().layout_no_wrap(duration, FontId::proportional(18.0), SUBHEADER_COL);
+            wave_rect.max.x -= d_galley.size().x + 8.0;
+            ui.painter().galley(pos2(rect.max.x - d_galley.size().x, rect.center().y - d_galley.size().y / 2.0), d_galley, SUBHEADER_COL);
+
+            // one bar per peak, mirrored around the middle, never fully flat so silence still shows up
+            let bar_width = wave_rect.width() / clip.peaks.len().max(1) as f32;
+            for (i, peak) in clip.peaks.iter().enumerate() {
+                let height = (peak * wave_rect.height()).max(2.0);
+                let x = wave_rect.min.x + bar_width * i as f32;
+                let bar = Rect::from_center_size(pos2(x + bar_width / 2.0, wave_rect.center().y), vec2((bar_width - 2.0).max(1.0), height));
+                ui.painter().rect_filled(bar, Rounding::same(0.0), Color32::WHITE);
+            }
+        },
+    }
+
+    response
+}
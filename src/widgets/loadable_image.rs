@@ -24,5 +24,25 @@ pub fn loadable_image(ui: &mut egui::Ui, img: &LoadableImage, rect: egui::Rect,
             let img = egui::Image::new(ImageSource::Texture(SizedTexture { id: *img, size: rect.size() }));
             img.fit_to_exact_size(rect.size()).rounding(Rounding::same(radius)).paint_at(ui, rect);
         },
+        LoadableImage::Failed(_, _) => {
+            ui.painter().rect_filled(rect, Rounding::same(radius), fill_color);
+            // photo with a line through it, there's no proper broken image glyph
+            let size = rect.width().min(rect.height()) / 2.0;
+            ui.painter().text(rect.center(), Align2::CENTER_CENTER, "", FontId::proportional(size), Color32::WHITE);
+            let slash = Rect::from_center_size(rect.center(), vec2(size, size));
+            ui.painter().line_segment([slash.left_bottom(), slash.right_top()], Stroke::new((size / 16.0).max(1.5), Color32::WHITE));
+        },
+    }
+}
+
+/// Click to retry for a failed image that's drawn on its own. Anything inside a clickable row or card
+/// shouldn't use this, it'd steal the click, those just wait for the automatic retry
+pub fn image_retry(ui: &mut egui::Ui, img: &LoadableImage, rect: egui::Rect) {
+    let LoadableImage::Failed(reason, retry) = img else { return };
+    let resp = ui.interact(rect, ui.id().with(("image_retry", rect.min.x as i32, rect.min.y as i32)), Sense::click())
+        .on_hover_text(format!("Couldn't load image: {}\nClick to try again", reason));
+    if resp.clicked() {
+        retry.now();
+        ui.ctx().request_repaint();
     }
 }
\ No newline at end of file
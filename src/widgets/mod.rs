@@ -4,4 +4,7 @@ pub mod button;
 pub mod segoe_boot_spinner;
 pub mod user_info;
 pub mod loadable_image;
-pub mod page_header;
\ No newline at end of file
+pub mod page_header;
+pub mod session_card;
+pub mod record_card;
+pub mod audio_card;
\ No newline at end of file
@@ -0,0 +1,66 @@
+use egui::{text::{LayoutJob, TextWrapping}, vec2, Color32, FontId, Rect, RichText, Rounding, Sense};
+
+use crate::{api::client::Record, image::ResDbImageCache, widgets::button::metro_button, HOVER_COL, SUBHEADER_COL};
+use super::loadable_image::loadable_image;
+
+/// Square thumbnail with the item name under it
+pub const RECORD_CARD_SIZE: egui::Vec2 = vec2(240.0, 276.0);
+
+/// Draws a shared item into `rect`. Clicking it is up to the caller
+pub fn record_card(ui: &mut egui::Ui, rect: Rect, cache: &mut ResDbImageCache, record: &Record, id: egui::Id) -> egui::Response {
+    let response = ui.interact(rect, id, Sense::click());
+    if response.hovered() {
+        ui.painter().rect_filled(rect.expand(4.0), Rounding::same(0.0), HOVER_COL);
+    }
+
+    let mut thumb_rect = rect;
+    thumb_rect.max.y = thumb_rect.min.y + rect.width();
+    if let Some(uri) = &record.thumbnail_uri {
+        let loadable = cache.get_image(uri);
+        loadable_image(ui, &loadable, thumb_rect, "", Color32::from_gray(34), 0.0, false);
+    } else {
+        ui.painter().rect_filled(thumb_rect, Rounding::same(0.0), Color32::from_gray(34));
+        ui.painter().text(thumb_rect.center(), egui::Align2::CENTER_CENTER, "", FontId::proportional(64.0), Color32::WHITE);
+    }
+
+    let mut name_job = LayoutJob::simple_singleline(record.name.clone(), FontId::proportional(24.0), Color32::WHITE);
+    name_job.wrap = TextWrapping::truncate_at_width(rect.width());
+    let name_galley = ui.painter().layout_job(name_job);
+    ui.painter().galley(thumb_rect.left_bottom() + vec2(0.0, 8.0), name_galley, Color32::WHITE);
+
+    response
+}
+
+/// Owner, path and asset for the details popover, plus copying the link
+pub fn record_details(ui: &mut egui::Ui, record: &Record) {
+    ui.set_max_width(420.0);
+    ui.style_mut().spacing.item_spacing.y = 6.0;
+
+    ui.label(RichText::new(&record.name).size(24.0).color(Color32::WHITE));
+    if let Some(description) = &record.description {
+        if !description.is_empty() {
+            ui.label(RichText::new(description).size(18.0).color(Color32::WHITE));
+        }
+    }
+
+    let owner = record.owner_name.clone().unwrap_or_else(|| record.owner_id.clone());
+    let rows = [
+        ("Owner", Some(owner)),
+        ("Type", Some(record.record_type.clone()).filter(|t| !t.is_empty())),
+        ("Path", record.path.clone()),
+        ("Asset", record.asset_uri.clone()),
+        ("Tags", Some(record.tags.join(", ")).filter(|t| !t.is_empty())),
+    ];
+    for (label, value) in rows {
+        if let Some(value) = value {
+            ui.label(RichText::new(label).size(16.0).color(SUBHEADER_COL));
+            ui.add(egui::Label::new(RichText::new(value).size(18.0).color(Color32::WHITE)).wrap());
+        }
+    }
+
+    ui.style_mut().spacing.interact_size.y = 48.0;
+    if metro_button(ui, "Copy link", Some(("", 20.0))).clicked() {
+        ui.ctx().copy_text(record.link());
+        ui.memory_mut(|mem| mem.close_popup());
+    }
+}
@@ -0,0 +1,44 @@
+use egui::{text::{LayoutJob, TextWrapping}, vec2, Color32, FontId, Rect, Rounding, Sense};
+
+use crate::{backend::thread::SessionUpdate, image::ResDbImageCache, HOVER_COL, SUBHEADER_COL};
+use super::loadable_image::loadable_image;
+
+/// How big a session card is, thumbnail on top and 3 lines of text under it
+pub const SESSION_CARD_SIZE: egui::Vec2 = vec2(320.0, 264.0);
+
+/// Thumbnail, name, host and user count, drawn into `rect`. Clicking it is up to the caller
+pub fn session_card(ui: &mut egui::Ui, rect: Rect, cache: &mut ResDbImageCache, session: &SessionUpdate, id: egui::Id) -> egui::Response {
+    let response = ui.interact(rect, id, Sense::click());
+    if response.hovered() {
+        ui.painter().rect_filled(rect.expand(4.0), Rounding::same(0.0), HOVER_COL);
+    }
+
+    // 16:9, same as the thumbnails the game takes
+    let mut thumb_rect = rect;
+    thumb_rect.max.y = thumb_rect.min.y + rect.width() * 9.0 / 16.0;
+    if let Some(url) = &session.thumbnail_url {
+        let loadable = cache.get_image(url);
+        loadable_image(ui, &loadable, thumb_rect, "", Color32::from_gray(34), 0.0, false);
+    } else {
+        ui.painter().rect_filled(thumb_rect, Rounding::same(0.0), Color32::from_gray(34));
+        ui.painter().text(thumb_rect.center(), egui::Align2::CENTER_CENTER, "", FontId::proportional(48.0), Color32::WHITE);
+    }
+
+    let mut name_job = LayoutJob::simple_singleline(session.name.clone(), FontId::proportional(24.0), Color32::WHITE);
+    name_job.wrap = TextWrapping::truncate_at_width(rect.width());
+    let name_galley = ui.painter().layout_job(name_job);
+    let name_pos = thumb_rect.left_bottom() + vec2(0.0, 8.0);
+    let name_height = name_galley.size().y;
+    ui.painter().galley(name_pos, name_galley, Color32::WHITE);
+
+    let users = if session.has_ended {
+        "Ended".to_owned()
+    } else {
+        format!("{}/{} users", session.joined_users, session.max_users)
+    };
+    let host_pos = name_pos + vec2(0.0, name_height + 4.0);
+    ui.painter().text(host_pos, egui::Align2::LEFT_TOP, format!("Hosted by {}", session.host_username), FontId::proportional(18.0), SUBHEADER_COL);
+    ui.painter().text(host_pos + vec2(0.0, 24.0), egui::Align2::LEFT_TOP, users, FontId::proportional(18.0), SUBHEADER_COL);
+
+    response
+}
@@ -173,6 +173,7 @@ pub fn user_info_widget(ui: &mut egui::Ui, cache: &mut ResDbImageCache, info: Us
     let cirlcle_rect = egui::Rect::from_center_size(circle_pos, vec2(pfp_radius * 2.0, pfp_radius * 2.0));
 
     let blank_ref = &"".to_owned();
+    let relationship = if let UserInfoVariant::Contact(contact) = &info { contact.relationship_label() } else { None };
 
     let (main, sub, needs_draw) = {
         match info {
@@ -230,7 +231,7 @@ pub fn user_info_widget(ui: &mut egui::Ui, cache: &mut ResDbImageCache, info: Us
             painter.circle(center, 4.0, col, Stroke::NONE);
         }
         
-        painter.text(bound_rect.min + vec2(0.0, 34.0), Align2::LEFT_TOP, subtext, FontId::proportional(24.0), Color32::GRAY);
+        painter.text(bound_rect.min + vec2(0.0, 34.0), Align2::LEFT_TOP, relationship.unwrap_or(&subtext), FontId::proportional(24.0), Color32::GRAY);
     }
 
 